@@ -0,0 +1,12 @@
+use std::env;
+
+// ARMv6-M cores have neither CAS instructions nor the DWT cycle counter, see
+// `cortex_m_port::wait`. `kaori_model` is set by hand to run the model tests, see
+// `utils::atomic`.
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(armv6m)");
+    println!("cargo::rustc-check-cfg=cfg(kaori_model)");
+    if env::var("TARGET").unwrap().starts_with("thumbv6m-") {
+        println!("cargo::rustc-cfg=armv6m");
+    }
+}
@@ -1,6 +1,643 @@
-use kaori_hsm::{StateMachine, TopState};
+use crate::event::event_queue::{EventQueue, EventQueueStorage};
+use crate::kernel::{Dispatch, Kernel, MAX_ACTIVE_OBJECTS};
+use core::cell::UnsafeCell;
+use core::mem::ManuallyDrop;
+use core::ptr::NonNull;
+use kaori_hsm::{InitStateMachine, StateMachine, TopState};
+use portable_atomic as atomic;
 
-struct ActiveObject<UserStateMachine: TopState>{
-    pub(crate) evt_queue : EvtQueue<<UserStateMachine as TopState>::Evt>,
-    pub(crate) state_machine: StateMachine<UserStateMachine>
+pub type Priority = u8;
+
+// Couples a kaori-hsm state machine with the queue of events it processes.
+// Events are handled one at a time, each one being run to completion before the next is popped.
+pub struct ActiveObject<'a, UserStateMachine: TopState> {
+    pub(crate) evt_queue: EventQueue<'a, <UserStateMachine as TopState>::Evt>,
+    pub(crate) state_machine: UnsafeCell<Option<StateMachine<UserStateMachine>>>,
+    // Event being dispatched, until it is moved to a deferral queue
+    dispatched_evt: UnsafeCell<Option<NonNull<<UserStateMachine as TopState>::Evt>>>,
+    prio: Priority,
+    started: atomic::AtomicBool,
+    kernel: &'a dyn Kernel,
+}
+
+unsafe impl<'a, UserStateMachine: TopState + Send> Sync for ActiveObject<'a, UserStateMachine> where
+    <UserStateMachine as TopState>::Evt: Send
+{
+}
+
+impl<'a, UserStateMachine: TopState> ActiveObject<'a, UserStateMachine> {
+    pub const fn new<const CAPACITY: usize>(
+        evt_queue_storage: &'a EventQueueStorage<<UserStateMachine as TopState>::Evt, CAPACITY>,
+        prio: Priority,
+        kernel: &'a dyn Kernel,
+    ) -> ActiveObject<'a, UserStateMachine> {
+        assert!(
+            prio > 0 && prio as usize <= MAX_ACTIVE_OBJECTS,
+            "Active object priority out of range"
+        );
+        ActiveObject {
+            evt_queue: EventQueue::from(evt_queue_storage),
+            state_machine: UnsafeCell::new(None),
+            dispatched_evt: UnsafeCell::new(None),
+            prio,
+            started: atomic::AtomicBool::new(false),
+            kernel,
+        }
+    }
+
+    pub const fn get_priority(&self) -> Priority {
+        self.prio
+    }
+
+    // Take ownership of the user state machine, execute its topmost initial transition and
+    // register the active object to its kernel
+    pub fn start(&'static self, user_state_machine: UserStateMachine)
+    where
+        UserStateMachine: Send,
+        <UserStateMachine as TopState>::Evt: Send,
+    {
+        assert!(
+            !self.started.swap(true, atomic::Ordering::Acquire),
+            "Active object already started"
+        );
+        let state_machine = InitStateMachine::from(user_state_machine).init();
+        unsafe {
+            *self.state_machine.get() = Some(state_machine);
+        }
+        self.kernel.register(self);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(atomic::Ordering::Relaxed)
+    }
+
+    // Post an event from thread context. Overflowing the event queue is a fatal error.
+    pub fn post(&self, evt: <UserStateMachine as TopState>::Evt) {
+        if self.post_with_margin(evt, 0).is_err() {
+            panic!("Event queue of active object {} overflowed", self.prio);
+        }
+    }
+
+    // Post an event from an interrupt handler. Overflowing the event queue is a fatal error.
+    pub fn post_from_isr(&self, evt: <UserStateMachine as TopState>::Evt) {
+        if self.post_from_isr_with_margin(evt, 0).is_err() {
+            panic!("Event queue of active object {} overflowed", self.prio);
+        }
+    }
+
+    // Post an event from thread context, provided that at least `margin` entries of the event
+    // queue are still free afterwards. Otherwise the event is handed back to the caller, which
+    // is expected to cope with it rather than treating it as a fatal error.
+    pub fn post_with_margin(
+        &self,
+        evt: <UserStateMachine as TopState>::Evt,
+        margin: usize,
+    ) -> Result<(), <UserStateMachine as TopState>::Evt> {
+        self.evt_queue.post_with_margin(evt, margin)?;
+        self.kernel.on_post(self.prio);
+        Ok(())
+    }
+
+    pub fn post_from_isr_with_margin(
+        &self,
+        evt: <UserStateMachine as TopState>::Evt,
+        margin: usize,
+    ) -> Result<(), <UserStateMachine as TopState>::Evt> {
+        self.evt_queue.post_with_margin(evt, margin)?;
+        self.kernel.on_post_from_isr(self.prio);
+        Ok(())
+    }
+
+    /// Post an urgent event at the front of the event queue, so that it is the next one
+    /// dispatched. Overflowing the event queue is a fatal error.
+    /// # Safety
+    /// Must only be called from a run-to-completion step of the active object: only self-posted
+    /// events may jump the queue.
+    pub unsafe fn post_lifo(&self, evt: <UserStateMachine as TopState>::Evt) {
+        if self.evt_queue.post_front(evt).is_err() {
+            panic!("Event queue of active object {} overflowed", self.prio);
+        }
+        self.kernel.on_post(self.prio);
+    }
+
+    // Park an event in a deferral queue, typically one that is not welcome in the current
+    // state. Events are moved rather than copied: deferring an event being dispatched only takes
+    // a clone of its handle, see `defer_dispatched()` for events that cannot be cloned. The
+    // event is handed back if the deferral queue is full.
+    pub fn defer(
+        &self,
+        defer_queue: &EventQueue<<UserStateMachine as TopState>::Evt>,
+        evt: <UserStateMachine as TopState>::Evt,
+    ) -> Result<(), <UserStateMachine as TopState>::Evt> {
+        defer_queue.post(evt)
+    }
+
+    /// Move the event being dispatched to a deferral queue instead of dropping it at the end of
+    /// the run-to-completion step, so that events owning their slot (e.g. a pool `Box`) can be
+    /// deferred as well. The event stays borrowed by the state machine until the end of the
+    /// step. Return `false` if the deferral queue is full, the event being dropped as usual.
+    /// # Safety
+    /// Must only be called from a run-to-completion step of the active object.
+    pub unsafe fn defer_dispatched(
+        &self,
+        defer_queue: &EventQueue<<UserStateMachine as TopState>::Evt>,
+    ) -> bool {
+        let dispatched_evt = &mut *self.dispatched_evt.get();
+        let evt = dispatched_evt.expect("No event being dispatched");
+        // The event is owned by either the deferral queue or the dispatch, never both
+        match defer_queue.post(evt.as_ptr().read()) {
+            Ok(()) => {
+                *dispatched_evt = None;
+                true
+            }
+            Err(evt) => {
+                core::mem::forget(evt);
+                false
+            }
+        }
+    }
+
+    /// Move the oldest event of a deferral queue to the front of the event queue, so that it is
+    /// the next one dispatched. Return `false` if no event was deferred.
+    /// # Safety
+    /// Must only be called from a run-to-completion step of the active object, which must be the
+    /// only context recalling events from `defer_queue`.
+    pub unsafe fn recall(
+        &self,
+        defer_queue: &EventQueue<<UserStateMachine as TopState>::Evt>,
+    ) -> bool {
+        if let Some(evt) = defer_queue.pop() {
+            self.post_lifo(evt);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Pop one event and run the state machine to completion on it. The event is dropped
+    /// afterwards, handing its slot back to the memory pool it was allocated from, unless it
+    /// has been deferred. Return `false` if no event was available.
+    /// # Safety
+    /// Must only be called from the execution context owning the active object, after `start()`.
+    pub unsafe fn dispatch_one(&self) -> bool {
+        let state_machine = (*self.state_machine.get())
+            .as_mut()
+            .expect("Active object not started");
+        if let Some(evt) = self.evt_queue.pop() {
+            let evt = ManuallyDrop::new(evt);
+            *self.dispatched_evt.get() = Some(NonNull::from(&*evt));
+            state_machine.dispatch(&evt);
+            if (*self.dispatched_evt.get()).take().is_some() {
+                drop(ManuallyDrop::into_inner(evt));
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<'a, UserStateMachine: TopState + Send> Dispatch for ActiveObject<'a, UserStateMachine>
+where
+    <UserStateMachine as TopState>::Evt: Send,
+{
+    fn get_priority(&self) -> Priority {
+        self.prio
+    }
+
+    fn has_events(&self) -> bool {
+        !self.evt_queue.is_empty()
+    }
+
+    unsafe fn dispatch_one(&self) -> bool {
+        Self::dispatch_one(self)
+    }
+}
+
+// Destination of events produced by kernel services (time events, publish-subscribe...)
+// regardless of the state machine the receiving active object runs
+pub trait EventSink<E>: Sync {
+    fn get_priority(&self) -> Priority;
+    fn get_kernel(&self) -> &dyn Kernel;
+    fn post(&self, evt: E);
+    fn post_from_isr(&self, evt: E);
+    // Return `false` if the event was dropped, fewer than `margin` entries of the event queue
+    // being free afterwards otherwise
+    fn post_from_isr_with_margin(&self, evt: E, margin: usize) -> bool;
+}
+
+impl<'a, UserStateMachine: TopState + Send, E> EventSink<E> for ActiveObject<'a, UserStateMachine>
+where
+    <UserStateMachine as TopState>::Evt: Send + From<E>,
+{
+    fn get_priority(&self) -> Priority {
+        self.prio
+    }
+
+    fn get_kernel(&self) -> &dyn Kernel {
+        self.kernel
+    }
+
+    fn post(&self, evt: E) {
+        Self::post(self, evt.into())
+    }
+
+    fn post_from_isr(&self, evt: E) {
+        Self::post_from_isr(self, evt.into())
+    }
+
+    fn post_from_isr_with_margin(&self, evt: E, margin: usize) -> bool {
+        Self::post_from_isr_with_margin(self, evt.into(), margin).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_box;
+    use crate::kernel::qv::QvKernel;
+    use crate::memory_allocation::allocator::memory_pool_allocator::{
+        get_words_per_pool, MemPoolId, MemoryPool, SlotPool,
+    };
+    use kaori_hsm::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    const POOL0_ID: MemPoolId = 0;
+    const POOL0_WORDS_PER_SLOT: usize = 2;
+    const POOL0_SLOTS_PER_POOL: usize = 2;
+    const POOL0_WORDS_PER_POOL: usize =
+        get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+    static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+        SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+    static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+    define_box!(test_box, MEMORY_POOL_0);
+
+    #[derive(Debug)]
+    enum BlinkyEvt {
+        ButtonPressed,
+        ButtonReleased,
+        Brightness(test_box::Box<u32>),
+    }
+
+    struct Blinky {
+        sender: Sender<String>,
+    }
+
+    impl Blinky {
+        fn post_string(&self, s: &str) {
+            self.sender.send(String::from(s)).unwrap();
+        }
+    }
+
+    impl TopState for Blinky {
+        type Evt = BlinkyEvt;
+
+        fn init(&mut self) -> InitResult<Self> {
+            self.post_string("TOP_INIT");
+            init_transition!(LedOff)
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<LedOff> for Blinky {
+        fn handle(&mut self, evt: &BlinkyEvt) -> HandleResult<Self> {
+            match evt {
+                BlinkyEvt::ButtonPressed => transition!(LedOn),
+                _ => ignored!(),
+            }
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<LedOn> for Blinky {
+        fn entry(&mut self) {
+            self.post_string("LED_ON");
+        }
+
+        fn exit(&mut self) {
+            self.post_string("LED_OFF");
+        }
+
+        fn handle(&mut self, evt: &BlinkyEvt) -> HandleResult<Self> {
+            match evt {
+                BlinkyEvt::ButtonReleased => transition!(LedOff),
+                BlinkyEvt::Brightness(brightness) => {
+                    self.post_string(&format!("BRIGHTNESS_{}", **brightness));
+                    handled!()
+                }
+                _ => ignored!(),
+            }
+        }
+    }
+
+    fn assert_eq_sm_output(receiver: &Receiver<String>, expectations: &[&str]) {
+        let output: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(output, expectations);
+    }
+
+    static EVT_QUEUE_STORAGE: EventQueueStorage<BlinkyEvt, 4> = EventQueueStorage::new();
+    static KERNEL: QvKernel = QvKernel::new();
+    static BLINKY: ActiveObject<Blinky> = ActiveObject::new(&EVT_QUEUE_STORAGE, 1, &KERNEL);
+
+    #[test]
+    #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+    fn active_object_test_0() {
+        let (sender, receiver) = channel();
+        assert!(!BLINKY.is_started());
+        BLINKY.start(Blinky { sender });
+        assert_eq_sm_output(&receiver, &["TOP_INIT"]);
+
+        unsafe {
+            assert!(!BLINKY.dispatch_one());
+
+            BLINKY.post(BlinkyEvt::ButtonPressed);
+            BLINKY.post_from_isr(BlinkyEvt::Brightness(test_box::Box::new(10)));
+            BLINKY.post(BlinkyEvt::Brightness(test_box::Box::new(20)));
+            BLINKY.post(BlinkyEvt::ButtonReleased);
+            assert_eq_sm_output(&receiver, &[]);
+
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["LED_ON"]);
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["BRIGHTNESS_10"]);
+
+            // The slot of the first Brightness event is available again after its dispatch
+            BLINKY.post(BlinkyEvt::Brightness(test_box::Box::new(30)));
+
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["BRIGHTNESS_20"]);
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["LED_OFF"]);
+
+            // Ignored in LedOff, the event slot is still freed
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &[]);
+            assert!(!BLINKY.dispatch_one());
+
+            let boxes: Vec<_> = (0..POOL0_SLOTS_PER_POOL as u32)
+                .map(test_box::Box::new)
+                .collect();
+            assert_eq!(boxes.len(), POOL0_SLOTS_PER_POOL);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+    fn post_lifo_and_margin_test() {
+        static EVT_QUEUE_STORAGE: EventQueueStorage<BlinkyEvt, 4> = EventQueueStorage::new();
+        static BLINKY: ActiveObject<Blinky> = ActiveObject::new(&EVT_QUEUE_STORAGE, 3, &KERNEL);
+        let (sender, receiver) = channel();
+        BLINKY.start(Blinky { sender });
+        assert_eq_sm_output(&receiver, &["TOP_INIT"]);
+
+        BLINKY.post(BlinkyEvt::ButtonReleased);
+        assert!(BLINKY
+            .post_with_margin(BlinkyEvt::ButtonReleased, 2)
+            .is_ok());
+        assert!(matches!(
+            BLINKY.post_from_isr_with_margin(BlinkyEvt::ButtonPressed, 2),
+            Err(BlinkyEvt::ButtonPressed)
+        ));
+
+        unsafe {
+            // Dispatched ahead of the events already queued
+            BLINKY.post_lifo(BlinkyEvt::ButtonPressed);
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["LED_ON"]);
+            assert!(BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &["LED_OFF"]);
+            assert!(BLINKY.dispatch_one());
+            assert!(!BLINKY.dispatch_one());
+            assert_eq_sm_output(&receiver, &[]);
+        }
+    }
+
+    mod deferral {
+        use super::*;
+        use crate::define_arc;
+        use crate::event::event_queue::EventQueue;
+
+        const POOL1_ID: MemPoolId = 1;
+        const POOL1_WORDS_PER_SLOT: usize = 2;
+        const POOL1_SLOTS_PER_POOL: usize = 4;
+        const POOL1_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL1_SLOTS_PER_POOL, POOL1_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL1_WORDS_PER_POOL> =
+            SlotPool::<POOL1_WORDS_PER_POOL>::new(POOL1_WORDS_PER_SLOT, POOL1_ID);
+        static MEMORY_POOL_1: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+        define_box!(test_box, MEMORY_POOL_1);
+        define_arc!(test_arc, test_box);
+
+        const POOL2_ID: MemPoolId = 2;
+        const POOL2_WORDS_PER_SLOT: usize = 1;
+        const POOL2_SLOTS_PER_POOL: usize = 4;
+        const POOL2_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL2_SLOTS_PER_POOL, POOL2_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL_2: SlotPool<POOL2_WORDS_PER_POOL> =
+            SlotPool::<POOL2_WORDS_PER_POOL>::new(POOL2_WORDS_PER_SLOT, POOL2_ID);
+        static MEMORY_POOL_2: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_2);
+        define_box!(job_box, MEMORY_POOL_2);
+
+        #[derive(Debug)]
+        enum ServerEvt {
+            Request(test_arc::Arc<u32>),
+            // Cannot be cloned, the event being dispatched is deferred as is
+            Job(job_box::Box<u32>),
+            Done,
+        }
+
+        // Serves one request at a time, deferring the requests received while busy
+        struct Server {
+            active_object: &'static ActiveObject<'static, Server>,
+            defer_queue: &'static EventQueue<'static, ServerEvt>,
+            sender: Sender<String>,
+        }
+
+        impl TopState for Server {
+            type Evt = ServerEvt;
+
+            fn init(&mut self) -> InitResult<Self> {
+                init_transition!(Idle)
+            }
+        }
+
+        #[state(super_state= Top)]
+        impl State<Idle> for Server {
+            fn entry(&mut self) {
+                unsafe {
+                    self.active_object.recall(self.defer_queue);
+                }
+            }
+
+            fn handle(&mut self, evt: &ServerEvt) -> HandleResult<Self> {
+                match evt {
+                    ServerEvt::Request(request) => {
+                        self.sender.send(format!("SERVING_{}", **request)).unwrap();
+                        transition!(Busy)
+                    }
+                    ServerEvt::Job(job) => {
+                        self.sender.send(format!("SERVING_JOB_{}", **job)).unwrap();
+                        transition!(Busy)
+                    }
+                    _ => ignored!(),
+                }
+            }
+        }
+
+        #[state(super_state= Top)]
+        impl State<Busy> for Server {
+            fn handle(&mut self, evt: &ServerEvt) -> HandleResult<Self> {
+                match evt {
+                    ServerEvt::Request(request) => {
+                        let deferred = ServerEvt::Request(request.clone());
+                        if self.active_object.defer(self.defer_queue, deferred).is_ok() {
+                            self.sender.send(format!("DEFERRED_{}", **request)).unwrap();
+                        } else {
+                            self.sender.send(format!("REJECTED_{}", **request)).unwrap();
+                        }
+                        handled!()
+                    }
+                    ServerEvt::Job(job) => {
+                        if unsafe { self.active_object.defer_dispatched(self.defer_queue) } {
+                            self.sender.send(format!("DEFERRED_JOB_{}", **job)).unwrap();
+                        } else {
+                            self.sender.send(format!("REJECTED_JOB_{}", **job)).unwrap();
+                        }
+                        handled!()
+                    }
+                    ServerEvt::Done => transition!(Idle),
+                }
+            }
+        }
+
+        static EVT_QUEUE_STORAGE: EventQueueStorage<ServerEvt, 4> = EventQueueStorage::new();
+        static DEFER_QUEUE_STORAGE: EventQueueStorage<ServerEvt, 2> = EventQueueStorage::new();
+        static DEFER_QUEUE: EventQueue<ServerEvt> = EventQueue::from(&DEFER_QUEUE_STORAGE);
+        static KERNEL: QvKernel = QvKernel::new();
+        static SERVER: ActiveObject<Server> = ActiveObject::new(&EVT_QUEUE_STORAGE, 1, &KERNEL);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn defer_recall_test() {
+            let (sender, receiver) = channel();
+            SERVER.start(Server {
+                active_object: &SERVER,
+                defer_queue: &DEFER_QUEUE,
+                sender,
+            });
+
+            for round in 0..3 {
+                for i in 0..4 {
+                    SERVER.post(ServerEvt::Request(test_arc::Arc::new(round * 10 + i)));
+                }
+                while KERNEL.step(|| {}) {}
+                let expected_0 = [
+                    format!("SERVING_{}", round * 10),
+                    format!("DEFERRED_{}", round * 10 + 1),
+                    format!("DEFERRED_{}", round * 10 + 2),
+                    format!("REJECTED_{}", round * 10 + 3),
+                ];
+                assert_eq_sm_output(&receiver, &expected_0.each_ref().map(String::as_str));
+
+                // A recalled request is served before the ones posted after it
+                SERVER.post(ServerEvt::Done);
+                SERVER.post(ServerEvt::Request(test_arc::Arc::new(round * 10 + 4)));
+                SERVER.post(ServerEvt::Done);
+                while KERNEL.step(|| {}) {}
+                let expected_1 = [
+                    format!("SERVING_{}", round * 10 + 1),
+                    format!("DEFERRED_{}", round * 10 + 4),
+                    format!("SERVING_{}", round * 10 + 2),
+                ];
+                assert_eq_sm_output(&receiver, &expected_1.each_ref().map(String::as_str));
+
+                SERVER.post(ServerEvt::Done);
+                while KERNEL.step(|| {}) {}
+                assert_eq_sm_output(&receiver, &[&format!("SERVING_{}", round * 10 + 4)]);
+                SERVER.post(ServerEvt::Done);
+                while KERNEL.step(|| {}) {}
+                assert_eq_sm_output(&receiver, &[]);
+
+                // Every request has been released exactly once
+                let boxes: Vec<_> = (0..POOL1_SLOTS_PER_POOL)
+                    .map(|i| test_box::Box::new([i; POOL1_WORDS_PER_SLOT]))
+                    .collect();
+                assert_eq!(boxes.len(), POOL1_SLOTS_PER_POOL);
+            }
+        }
+
+        static JOB_EVT_QUEUE_STORAGE: EventQueueStorage<ServerEvt, 4> = EventQueueStorage::new();
+        static JOB_DEFER_QUEUE_STORAGE: EventQueueStorage<ServerEvt, 2> = EventQueueStorage::new();
+        static JOB_DEFER_QUEUE: EventQueue<ServerEvt> = EventQueue::from(&JOB_DEFER_QUEUE_STORAGE);
+        static JOB_KERNEL: QvKernel = QvKernel::new();
+        static JOB_SERVER: ActiveObject<Server> =
+            ActiveObject::new(&JOB_EVT_QUEUE_STORAGE, 1, &JOB_KERNEL);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn defer_box_test() {
+            let (sender, receiver) = channel();
+            JOB_SERVER.start(Server {
+                active_object: &JOB_SERVER,
+                defer_queue: &JOB_DEFER_QUEUE,
+                sender,
+            });
+
+            for round in 0..3 {
+                for i in 0..4 {
+                    JOB_SERVER.post(ServerEvt::Job(job_box::Box::new(round * 10 + i)));
+                }
+                while JOB_KERNEL.step(|| {}) {}
+                let expected_0 = [
+                    format!("SERVING_JOB_{}", round * 10),
+                    format!("DEFERRED_JOB_{}", round * 10 + 1),
+                    format!("DEFERRED_JOB_{}", round * 10 + 2),
+                    format!("REJECTED_JOB_{}", round * 10 + 3),
+                ];
+                assert_eq_sm_output(&receiver, &expected_0.each_ref().map(String::as_str));
+
+                // Only the deferred jobs still own their slot
+                let boxes: Vec<_> = (0..POOL2_SLOTS_PER_POOL)
+                    .map_while(|i| job_box::Box::try_new(i as u32).ok())
+                    .collect();
+                assert_eq!(boxes.len(), POOL2_SLOTS_PER_POOL - 2);
+                drop(boxes);
+
+                for i in 1..3 {
+                    JOB_SERVER.post(ServerEvt::Done);
+                    while JOB_KERNEL.step(|| {}) {}
+                    assert_eq_sm_output(&receiver, &[&format!("SERVING_JOB_{}", round * 10 + i)]);
+                }
+                JOB_SERVER.post(ServerEvt::Done);
+                while JOB_KERNEL.step(|| {}) {}
+                assert_eq_sm_output(&receiver, &[]);
+
+                // Every job has been released exactly once
+                let boxes: Vec<_> = (0..POOL2_SLOTS_PER_POOL)
+                    .map(|i| job_box::Box::new(i as u32))
+                    .collect();
+                assert_eq!(boxes.len(), POOL2_SLOTS_PER_POOL);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "overflowed")]
+    fn active_object_lifo_overflow_test() {
+        static EVT_QUEUE_STORAGE: EventQueueStorage<BlinkyEvt, 1> = EventQueueStorage::new();
+        let active_object: ActiveObject<Blinky> = ActiveObject::new(&EVT_QUEUE_STORAGE, 4, &KERNEL);
+        active_object.post(BlinkyEvt::ButtonPressed);
+        unsafe {
+            active_object.post_lifo(BlinkyEvt::ButtonPressed);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "overflowed")]
+    fn active_object_overflow_test() {
+        static EVT_QUEUE_STORAGE: EventQueueStorage<BlinkyEvt, 1> = EventQueueStorage::new();
+        let active_object: ActiveObject<Blinky> = ActiveObject::new(&EVT_QUEUE_STORAGE, 2, &KERNEL);
+        active_object.post(BlinkyEvt::ButtonPressed);
+        active_object.post(BlinkyEvt::ButtonPressed);
+    }
 }
@@ -1,5 +1,126 @@
-pub mod interrupt{
+pub mod interrupt {
     pub use cortex_m::interrupt::free;
 }
 
-pub use cortex_m::interrupt::Mutex;
+pub use cortex_m::interrupt::{CriticalSection, Mutex};
+
+// The scheduler is pended through PendSV, whose handler must call the kernel back. Active
+// objects activated from PendSV run at its priority, so PendSV must be given the lowest
+// exception priority.
+pub mod scheduler {
+    use cortex_m::peripheral::scb::{Exception, VectActive};
+    use cortex_m::peripheral::SCB;
+
+    pub fn pend() {
+        SCB::set_pendsv();
+    }
+
+    pub fn in_isr() -> bool {
+        !matches!(
+            SCB::vect_active(),
+            VectActive::ThreadMode | VectActive::Exception(Exception::PendSV)
+        )
+    }
+}
+
+pub mod tick {
+    use cortex_m::peripheral::syst::SystClkSource;
+    use cortex_m::peripheral::SYST;
+
+    // Make SysTick fire every `reload + 1` core clock cycles. Its handler is expected to call
+    // `TimeEventList::tick()`.
+    pub fn start_systick(syst: &mut SYST, reload: u32) {
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(reload);
+        syst.clear_current();
+        syst.enable_counter();
+        syst.enable_interrupt();
+    }
+}
+
+// Contexts waiting for a resource sleep with WFE, releases being signaled with SEV. Deadlines
+// are measured with the DWT cycle counter, which must have been enabled and the core clock
+// frequency given beforehand, and are only checked on wakeup: SysTick must be running for
+// timeouts to expire when no release happens. ARMv6-M lacks the cycle counter, so deadlines
+// are measured with the SysTick counter instead, which must be started with `start_systick()`.
+pub mod wait {
+    use core::time::Duration;
+    #[cfg(not(armv6m))]
+    use cortex_m::peripheral::DWT;
+    #[cfg(armv6m)]
+    use cortex_m::peripheral::SYST;
+    use portable_atomic as atomic;
+
+    static CORE_CLOCK_HZ: atomic::AtomicU32 = atomic::AtomicU32::new(0);
+
+    pub fn set_core_clock_hz(core_clock_hz: u32) {
+        CORE_CLOCK_HZ.store(core_clock_hz, atomic::Ordering::Relaxed);
+    }
+
+    pub struct Deadline {
+        last_cycle_count: u32,
+        remaining_cycles: u64,
+    }
+
+    impl Deadline {
+        pub fn after(timeout: Duration) -> Deadline {
+            let core_clock_hz = CORE_CLOCK_HZ.load(atomic::Ordering::Relaxed);
+            assert!(core_clock_hz != 0, "Core clock frequency not set");
+            let cycles = timeout.as_nanos() * core_clock_hz as u128 / 1_000_000_000;
+            Deadline {
+                last_cycle_count: get_cycle_count(),
+                remaining_cycles: cycles.try_into().unwrap_or(u64::MAX),
+            }
+        }
+
+        // Must be called at least once per cycle counter wrap-around, which the SysTick
+        // interrupt waking the core ensures on ARMv6-M
+        fn has_elapsed(&mut self) -> bool {
+            let cycle_count = get_cycle_count();
+            let elapsed = get_elapsed_cycles(self.last_cycle_count, cycle_count) as u64;
+            self.last_cycle_count = cycle_count;
+            self.remaining_cycles = self.remaining_cycles.saturating_sub(elapsed);
+            self.remaining_cycles == 0
+        }
+    }
+
+    #[cfg(not(armv6m))]
+    fn get_cycle_count() -> u32 {
+        DWT::cycle_count()
+    }
+
+    #[cfg(not(armv6m))]
+    fn get_elapsed_cycles(last_cycle_count: u32, cycle_count: u32) -> u32 {
+        cycle_count.wrapping_sub(last_cycle_count)
+    }
+
+    #[cfg(armv6m)]
+    fn get_cycle_count() -> u32 {
+        SYST::get_current()
+    }
+
+    // The SysTick counter counts down from its reload value
+    #[cfg(armv6m)]
+    fn get_elapsed_cycles(last_cycle_count: u32, cycle_count: u32) -> u32 {
+        let period = SYST::get_reload() + 1;
+        (last_cycle_count + period - cycle_count) % period
+    }
+
+    pub fn notify_all() {
+        cortex_m::asm::sev();
+    }
+
+    // Sleep until `woken` returns `true`, checked after each wakeup, or the deadline passes.
+    // Return `false` on timeout.
+    pub fn wait_until<F: Fn() -> bool>(deadline: &mut Deadline, woken: F) -> bool {
+        loop {
+            if woken() {
+                return true;
+            }
+            if deadline.has_elapsed() {
+                return false;
+            }
+            cortex_m::asm::wfe();
+        }
+    }
+}
@@ -1,4 +1,366 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use portable_atomic as atomic;
 
-pub struct EventQueue{
+pub struct EventQueueCell<T> {
+    ready: atomic::AtomicBool,
+    evt: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> EventQueueCell<T> {
+    const fn new() -> EventQueueCell<T> {
+        EventQueueCell {
+            ready: atomic::AtomicBool::new(false),
+            evt: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+// Statically allocatable storage backing an `EventQueue`
+pub struct EventQueueStorage<T, const CAPACITY: usize> {
+    cells: [EventQueueCell<T>; CAPACITY],
+}
+
+impl<T, const CAPACITY: usize> EventQueueStorage<T, CAPACITY> {
+    pub const fn new() -> EventQueueStorage<T, CAPACITY> {
+        assert!(CAPACITY > 0, "Event queue capacity cannot be null");
+        EventQueueStorage {
+            cells: [const { EventQueueCell::new() }; CAPACITY],
+        }
+    }
+}
+
+impl<T, const CAPACITY: usize> Default for EventQueueStorage<T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T: Send, const CAPACITY: usize> Sync for EventQueueStorage<T, CAPACITY> {}
+
+// Bounded multi-producer/single-consumer queue of events.
+//
+// Posting never waits on another context, so it is safe to post from interrupts
+// preempting a producer in the middle of its own post. A producer first reserves
+// an entry by incrementing `used`, then claims the cell at `tail` and finally
+// publishes it through the cell `ready` flag. The consumer only pops a cell once
+// it has been published and releases the entry by decrementing `used`, so that
+// claimed cells are never overwritten before having been consumed.
+//
+// The pop having freed a cell may only be acquired by a later reservation than
+// the one of the producer claiming it, so that claims are chained through `tail`
+// to hand that pop over to the producer before it writes the cell.
+pub struct EventQueue<'a, T> {
+    cells: &'a [EventQueueCell<T>],
+    head: atomic::AtomicUsize,
+    tail: atomic::AtomicUsize,
+    used: atomic::AtomicUsize,
+}
+
+unsafe impl<'a, T: Send> Sync for EventQueue<'a, T> {}
+
+impl<'a, T> EventQueue<'a, T> {
+    pub const fn from<const CAPACITY: usize>(
+        storage: &'a EventQueueStorage<T, CAPACITY>,
+    ) -> EventQueue<'a, T> {
+        EventQueue {
+            cells: &storage.cells,
+            head: atomic::AtomicUsize::new(0),
+            tail: atomic::AtomicUsize::new(0),
+            used: atomic::AtomicUsize::new(0),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.cells.len()
+    }
+
+    // Number of entries either holding an event or reserved by an in-flight post
+    pub fn len(&self) -> usize {
+        self.used.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn next_index(&self, index: usize) -> usize {
+        if index + 1 == self.capacity() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    fn prev_index(&self, index: usize) -> usize {
+        if index == 0 {
+            self.capacity() - 1
+        } else {
+            index - 1
+        }
+    }
+
+    // Reserve an entry, provided that `margin` entries are still free afterwards
+    fn reserve(&self, margin: usize) -> bool {
+        let mut used = self.used.load(atomic::Ordering::Relaxed);
+        loop {
+            if used + margin >= self.capacity() {
+                return false;
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used + 1,
+                atomic::Ordering::Acquire,
+                atomic::Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn claim_tail(&self) -> usize {
+        let mut tail = self.tail.load(atomic::Ordering::Relaxed);
+        loop {
+            match self.tail.compare_exchange_weak(
+                tail,
+                self.next_index(tail),
+                atomic::Ordering::AcqRel,
+                atomic::Ordering::Relaxed,
+            ) {
+                Ok(_) => return tail,
+                Err(current) => tail = current,
+            }
+        }
+    }
+
+    // Post an event at the back of the queue. The event is handed back if the queue is full.
+    pub fn post(&self, evt: T) -> Result<(), T> {
+        self.post_with_margin(evt, 0)
+    }
+
+    // Post an event at the back of the queue, provided that at least `margin` entries are
+    // still free afterwards. The event is handed back otherwise.
+    pub fn post_with_margin(&self, evt: T, margin: usize) -> Result<(), T> {
+        if !self.reserve(margin) {
+            return Err(evt);
+        }
+        let cell = &self.cells[self.claim_tail()];
+        debug_assert!(!cell.ready.load(atomic::Ordering::Acquire));
+        unsafe {
+            (*cell.evt.get()).write(evt);
+        }
+        cell.ready.store(true, atomic::Ordering::Release);
+        Ok(())
+    }
+
+    /// Post an event at the front of the queue, so that it is popped next. The event is handed
+    /// back if the queue is full.
+    /// # Safety
+    /// Must only be called by the consumer of the queue.
+    pub unsafe fn post_front(&self, evt: T) -> Result<(), T> {
+        // The reservation guarantees that producers are left enough free cells after `tail`,
+        // so that the one preceding `head` cannot be claimed by any of them
+        if !self.reserve(0) {
+            return Err(evt);
+        }
+        let head = self.prev_index(self.head.load(atomic::Ordering::Relaxed));
+        let cell = &self.cells[head];
+        (*cell.evt.get()).write(evt);
+        cell.ready.store(true, atomic::Ordering::Release);
+        self.head.store(head, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// # Safety
+    /// Only one context may act as the consumer of the queue.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(atomic::Ordering::Relaxed);
+        let cell = &self.cells[head];
+        if !cell.ready.load(atomic::Ordering::Acquire) {
+            // Either empty or the producer owning the head cell has not published it yet
+            return None;
+        }
+        let evt = (*cell.evt.get()).assume_init_read();
+        cell.ready.store(false, atomic::Ordering::Release);
+        self.head
+            .store(self.next_index(head), atomic::Ordering::Relaxed);
+        self.used.fetch_sub(1, atomic::Ordering::Release);
+        Some(evt)
+    }
+}
+
+impl<'a, T> Drop for EventQueue<'a, T> {
+    fn drop(&mut self) {
+        unsafe { while self.pop().is_some() {} }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_allocation::allocator::memory_pool_allocator::{
+        get_words_per_pool, MemPoolId, MemoryPool, SlotPool,
+    };
+    use std::thread;
+
+    #[test]
+    fn event_queue_test_0() {
+        static STORAGE: EventQueueStorage<u32, 4> = EventQueueStorage::new();
+        let evt_queue = EventQueue::from(&STORAGE);
+
+        unsafe {
+            assert!(evt_queue.is_empty());
+            assert_eq!(evt_queue.pop(), None);
+
+            for round in 0..3 {
+                for i in 0..4 {
+                    assert_eq!(evt_queue.post(round * 10 + i), Ok(()));
+                }
+                assert_eq!(evt_queue.len(), 4);
+                assert_eq!(evt_queue.post(0xFF), Err(0xFF));
+
+                assert_eq!(evt_queue.pop(), Some(round * 10));
+                assert_eq!(evt_queue.post(round * 10 + 4), Ok(()));
+                for i in 1..5 {
+                    assert_eq!(evt_queue.pop(), Some(round * 10 + i));
+                }
+                assert_eq!(evt_queue.pop(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn post_front_test() {
+        static STORAGE: EventQueueStorage<u32, 3> = EventQueueStorage::new();
+        let evt_queue = EventQueue::from(&STORAGE);
+
+        unsafe {
+            for round in 0..4 {
+                assert_eq!(evt_queue.post(round * 10 + 1), Ok(()));
+                assert_eq!(evt_queue.post_front(round * 10), Ok(()));
+                assert_eq!(evt_queue.post(round * 10 + 2), Ok(()));
+                assert_eq!(evt_queue.post_front(0xFF), Err(0xFF));
+                assert_eq!(evt_queue.post(0xFF), Err(0xFF));
+                for i in 0..3 {
+                    assert_eq!(evt_queue.pop(), Some(round * 10 + i));
+                }
+                assert_eq!(evt_queue.pop(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn post_with_margin_test() {
+        static STORAGE: EventQueueStorage<u32, 4> = EventQueueStorage::new();
+        let evt_queue = EventQueue::from(&STORAGE);
+
+        assert_eq!(evt_queue.post_with_margin(0, 4), Err(0));
+        assert_eq!(evt_queue.post_with_margin(0, 3), Ok(()));
+        assert_eq!(evt_queue.post_with_margin(1, 3), Err(1));
+        assert_eq!(evt_queue.post_with_margin(1, 2), Ok(()));
+        assert_eq!(evt_queue.post_with_margin(2, 1), Ok(()));
+        assert_eq!(evt_queue.post_with_margin(3, 1), Err(3));
+        assert_eq!(evt_queue.post_with_margin(3, 0), Ok(()));
+        assert_eq!(evt_queue.post_with_margin(4, 0), Err(4));
+        unsafe {
+            for i in 0..4 {
+                assert_eq!(evt_queue.pop(), Some(i));
+            }
+        }
+    }
+
+    mod boxed_events {
+        use super::*;
+        use crate::define_box;
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 2;
+        const POOL0_SLOTS_PER_POOL: usize = 3;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+        define_box!(test_box, MEMORY_POOL_0);
+
+        static STORAGE: EventQueueStorage<test_box::Box<(u16, usize)>, 3> =
+            EventQueueStorage::new();
+
+        #[test]
+        fn boxed_events_test_0() {
+            let evt_queue = EventQueue::from(&STORAGE);
+            for round in 0..4 {
+                for i in 0..POOL0_SLOTS_PER_POOL {
+                    evt_queue.post(test_box::Box::new((round, i))).unwrap();
+                }
+                for i in 0..POOL0_SLOTS_PER_POOL {
+                    let evt = unsafe { evt_queue.pop().unwrap() };
+                    assert_eq!(*evt, (round, i));
+                    // Dropping the event hands its slot back to the pool
+                }
+            }
+
+            // Events still queued are released along with the queue
+            evt_queue.post(test_box::Box::new((0xFE, 0))).unwrap();
+            drop(evt_queue);
+            let evts: Vec<_> = (0..POOL0_SLOTS_PER_POOL)
+                .map(|i| test_box::Box::new((0, i)))
+                .collect();
+            assert_eq!(evts.len(), POOL0_SLOTS_PER_POOL);
+        }
+    }
+
+    // Pop the events of several producers posting to `evt_queue`, checking their order
+    fn consume_producers<const NB_PRODUCERS: usize>(
+        evt_queue: &'static EventQueue<(usize, usize)>,
+        nb_events_per_producer: usize,
+    ) {
+        let mut join_handle_vec = Vec::new();
+        for producer in 0..NB_PRODUCERS {
+            join_handle_vec.push(thread::spawn(move || {
+                for i in 0..nb_events_per_producer {
+                    let mut evt = (producer, i);
+                    while let Err(rejected_evt) = evt_queue.post(evt) {
+                        evt = rejected_evt;
+                        thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        let mut next_expected = [0; NB_PRODUCERS];
+        let mut nb_received = 0;
+        while nb_received < NB_PRODUCERS * nb_events_per_producer {
+            if let Some((producer, i)) = unsafe { evt_queue.pop() } {
+                assert_eq!(next_expected[producer], i);
+                next_expected[producer] += 1;
+                nb_received += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        for join_handle in join_handle_vec.into_iter() {
+            join_handle.join().unwrap();
+        }
+        assert!(evt_queue.is_empty());
+    }
+
+    #[test]
+    fn multi_producer_test() {
+        const NB_EVENTS_PER_PRODUCER: usize = if cfg!(miri) { 200 } else { 10000 };
+        static STORAGE: EventQueueStorage<(usize, usize), 8> = EventQueueStorage::new();
+        static EVT_QUEUE: EventQueue<(usize, usize)> = EventQueue::from(&STORAGE);
+        consume_producers::<4>(&EVT_QUEUE, NB_EVENTS_PER_PRODUCER);
+    }
 
+    // The cells are reused as soon as they are popped, a producer claiming the cell of an
+    // event the consumer may just have read
+    #[test]
+    fn cell_reuse_test() {
+        const NB_EVENTS_PER_PRODUCER: usize = if cfg!(miri) { 100 } else { 10000 };
+        static STORAGE: EventQueueStorage<(usize, usize), 2> = EventQueueStorage::new();
+        static EVT_QUEUE: EventQueue<(usize, usize)> = EventQueue::from(&STORAGE);
+        consume_producers::<3>(&EVT_QUEUE, NB_EVENTS_PER_PRODUCER);
+    }
 }
@@ -1,2 +1,3 @@
-mod event_queue;
-
+pub(crate) mod event_queue;
+pub(crate) mod publish_subscribe;
+pub(crate) mod time_event;
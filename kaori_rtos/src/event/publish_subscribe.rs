@@ -0,0 +1,397 @@
+use crate::active_object::{EventSink, Priority};
+use crate::kernel::MAX_ACTIVE_OBJECTS;
+use crate::port::{interrupt, Mutex};
+use core::cell::Cell;
+use core::ops::Deref;
+use portable_atomic as atomic;
+
+// Index of the subscriber list an event is published to, ranging from 0 to the number of
+// signals of the bus
+pub trait Signal {
+    fn signal(&self) -> usize;
+}
+
+type SubscriberSlot<E> = Mutex<Cell<Option<&'static dyn EventSink<E>>>>;
+
+const fn prio_mask(prio: Priority) -> u32 {
+    1 << (prio - 1)
+}
+
+// Publish-subscribe bus multicasting reference-counted events to the active objects
+// subscribed to their signal. `E` is typically an `Arc` defined with `define_arc!`: the event
+// is allocated once by the publisher, every subscriber is handed a clone of it and its slot
+// returns to the memory pool once the last subscriber has completed its run-to-completion step.
+pub struct EventBus<E: 'static, const NB_SIGNALS: usize> {
+    // For each signal, bitmap of the priorities of the subscribed active objects
+    subscribers: [atomic::AtomicU32; NB_SIGNALS],
+    // Active objects having subscribed at least once, indexed by priority
+    sinks: [SubscriberSlot<E>; MAX_ACTIVE_OBJECTS],
+}
+
+impl<E, const NB_SIGNALS: usize> EventBus<E, NB_SIGNALS>
+where
+    E: Clone + Deref,
+    E::Target: Signal,
+{
+    pub const fn new() -> EventBus<E, NB_SIGNALS> {
+        EventBus {
+            subscribers: [const { atomic::AtomicU32::new(0) }; NB_SIGNALS],
+            sinks: [const { Mutex::new(Cell::new(None)) }; MAX_ACTIVE_OBJECTS],
+        }
+    }
+
+    fn subscriber_list(&self, signal: usize) -> &atomic::AtomicU32 {
+        assert!(signal < NB_SIGNALS, "Signal {} out of range", signal);
+        &self.subscribers[signal]
+    }
+
+    pub fn subscribe(&self, subscriber: &'static dyn EventSink<E>, signal: usize) {
+        let subscriber_list = self.subscriber_list(signal);
+        let prio = subscriber.get_priority();
+        interrupt::free(|cs| {
+            let sink = self.sinks[prio as usize - 1].borrow(cs);
+            if let Some(registered) = sink.get() {
+                assert!(
+                    core::ptr::addr_eq(registered, subscriber),
+                    "Priority {} already used by another subscriber",
+                    prio
+                );
+            }
+            sink.set(Some(subscriber));
+        });
+        subscriber_list.fetch_or(prio_mask(prio), atomic::Ordering::SeqCst);
+    }
+
+    // Events of the signal already posted to the subscriber are still delivered
+    pub fn unsubscribe(&self, subscriber: &dyn EventSink<E>, signal: usize) {
+        self.subscriber_list(signal).fetch_and(
+            !prio_mask(subscriber.get_priority()),
+            atomic::Ordering::SeqCst,
+        );
+    }
+
+    pub fn is_subscribed(&self, subscriber: &dyn EventSink<E>, signal: usize) -> bool {
+        self.subscriber_list(signal).load(atomic::Ordering::SeqCst)
+            & prio_mask(subscriber.get_priority())
+            != 0
+    }
+
+    // Post the event to every subscriber of its signal, highest priority first. The event is
+    // dropped right away if its signal has no subscriber.
+    pub fn publish(&self, evt: E) {
+        self.multicast(evt, |subscriber, evt| subscriber.post(evt))
+    }
+
+    pub fn publish_from_isr(&self, evt: E) {
+        self.multicast(evt, |subscriber, evt| subscriber.post_from_isr(evt))
+    }
+
+    fn get_sink(&self, prio: Priority) -> &'static dyn EventSink<E> {
+        interrupt::free(|cs| self.sinks[prio as usize - 1].borrow(cs).get())
+            .expect("Subscriber not registered")
+    }
+
+    fn multicast<F: Fn(&dyn EventSink<E>, E)>(&self, evt: E, post: F) {
+        // Subscribers are snapshotted, so that (un)subscribing during the publication only
+        // affects the following ones
+        let mut subscribers = self
+            .subscriber_list(evt.signal())
+            .load(atomic::Ordering::SeqCst);
+        if subscribers == 0 {
+            return;
+        }
+        // The scheduler is locked up to the highest priority subscriber for the whole multicast,
+        // so that no subscriber runs, and possibly publishes in turn, before every subscriber has
+        // received the event
+        let max_prio = (u32::BITS - subscribers.leading_zeros()) as Priority;
+        let kernel = self.get_sink(max_prio).get_kernel();
+        let lock_status = kernel.lock(max_prio);
+        while subscribers != 0 {
+            let prio = (u32::BITS - subscribers.leading_zeros()) as Priority;
+            subscribers &= !prio_mask(prio);
+            post(self.get_sink(prio), evt.clone());
+        }
+        kernel.unlock(lock_status);
+    }
+}
+
+impl<E, const NB_SIGNALS: usize> Default for EventBus<E, NB_SIGNALS>
+where
+    E: Clone + Deref,
+    E::Target: Signal,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::active_object::ActiveObject;
+    use crate::event::event_queue::EventQueueStorage;
+    use crate::kernel::qv::QvKernel;
+    use crate::memory_allocation::allocator::memory_pool_allocator::{
+        get_words_per_pool, MemPoolId, MemoryPool, SlotPool,
+    };
+    use crate::{define_arc, define_box};
+    use kaori_hsm::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    const POOL0_ID: MemPoolId = 0;
+    const POOL0_WORDS_PER_SLOT: usize = 4;
+    const POOL0_SLOTS_PER_POOL: usize = 2;
+    const POOL0_WORDS_PER_POOL: usize =
+        get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+    static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+        SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+    static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+    define_box!(test_box, MEMORY_POOL_0);
+    define_arc!(test_arc, test_box);
+
+    const TEMPERATURE_SIG: usize = 0;
+    const PRESSURE_SIG: usize = 1;
+    const NB_SIGNALS: usize = 2;
+
+    #[derive(Debug)]
+    enum Reading {
+        Temperature(i16),
+        Pressure(u16),
+    }
+
+    impl Signal for Reading {
+        fn signal(&self) -> usize {
+            match self {
+                Reading::Temperature(_) => TEMPERATURE_SIG,
+                Reading::Pressure(_) => PRESSURE_SIG,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    enum MonitorEvt {
+        Reading(test_arc::Arc<Reading>),
+    }
+
+    impl From<test_arc::Arc<Reading>> for MonitorEvt {
+        fn from(reading: test_arc::Arc<Reading>) -> Self {
+            MonitorEvt::Reading(reading)
+        }
+    }
+
+    struct Monitor {
+        name: &'static str,
+        sender: Sender<String>,
+    }
+
+    impl TopState for Monitor {
+        type Evt = MonitorEvt;
+
+        fn init(&mut self) -> InitResult<Self> {
+            init_transition!(Monitoring)
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<Monitoring> for Monitor {
+        fn handle(&mut self, evt: &MonitorEvt) -> HandleResult<Self> {
+            match evt {
+                MonitorEvt::Reading(reading) => {
+                    self.sender
+                        .send(format!("{}_{:?}", self.name, **reading))
+                        .unwrap();
+                }
+            }
+            handled!()
+        }
+    }
+
+    fn monitor(name: &'static str, sender: &Sender<String>) -> Monitor {
+        Monitor {
+            name,
+            sender: sender.clone(),
+        }
+    }
+
+    fn run_until_idle(receiver: &Receiver<String>) -> Vec<String> {
+        while KERNEL.step(|| {}) {}
+        receiver.try_iter().collect()
+    }
+
+    // All the slots of the pool are available, no published event leaked
+    fn assert_pool_empty() {
+        let boxes: Vec<_> = (0..POOL0_SLOTS_PER_POOL)
+            .map(|i| test_box::Box::new([i; POOL0_WORDS_PER_SLOT]))
+            .collect();
+        assert_eq!(boxes.len(), POOL0_SLOTS_PER_POOL);
+    }
+
+    static KERNEL: QvKernel = QvKernel::new();
+    static BUS: EventBus<test_arc::Arc<Reading>, NB_SIGNALS> = EventBus::new();
+    static STORAGE_LOGGER: EventQueueStorage<MonitorEvt, 4> = EventQueueStorage::new();
+    static STORAGE_DISPLAY: EventQueueStorage<MonitorEvt, 4> = EventQueueStorage::new();
+    static STORAGE_ALARM: EventQueueStorage<MonitorEvt, 4> = EventQueueStorage::new();
+    static LOGGER: ActiveObject<Monitor> = ActiveObject::new(&STORAGE_LOGGER, 1, &KERNEL);
+    static DISPLAY: ActiveObject<Monitor> = ActiveObject::new(&STORAGE_DISPLAY, 2, &KERNEL);
+    static ALARM: ActiveObject<Monitor> = ActiveObject::new(&STORAGE_ALARM, 3, &KERNEL);
+
+    #[test]
+    #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+    fn publish_subscribe_test_0() {
+        let (sender, receiver) = channel();
+        LOGGER.start(monitor("LOGGER", &sender));
+        DISPLAY.start(monitor("DISPLAY", &sender));
+        ALARM.start(monitor("ALARM", &sender));
+
+        BUS.subscribe(&LOGGER, TEMPERATURE_SIG);
+        BUS.subscribe(&LOGGER, PRESSURE_SIG);
+        BUS.subscribe(&DISPLAY, TEMPERATURE_SIG);
+        BUS.subscribe(&ALARM, PRESSURE_SIG);
+        assert!(BUS.is_subscribed(&ALARM, PRESSURE_SIG));
+        assert!(!BUS.is_subscribed(&ALARM, TEMPERATURE_SIG));
+
+        // Each event takes a single slot, whatever its number of subscribers
+        for _ in 0..3 {
+            BUS.publish(test_arc::Arc::new(Reading::Temperature(21)));
+            BUS.publish_from_isr(test_arc::Arc::new(Reading::Pressure(1013)));
+            assert_eq!(
+                run_until_idle(&receiver),
+                [
+                    "ALARM_Pressure(1013)",
+                    "DISPLAY_Temperature(21)",
+                    "LOGGER_Temperature(21)",
+                    "LOGGER_Pressure(1013)",
+                ]
+            );
+            assert_pool_empty();
+        }
+
+        // Already posted events are still delivered after unsubscribing
+        BUS.publish(test_arc::Arc::new(Reading::Pressure(990)));
+        BUS.unsubscribe(&ALARM, PRESSURE_SIG);
+        BUS.unsubscribe(&LOGGER, PRESSURE_SIG);
+        BUS.publish(test_arc::Arc::new(Reading::Pressure(980)));
+        assert_eq!(
+            run_until_idle(&receiver),
+            ["ALARM_Pressure(990)", "LOGGER_Pressure(990)"]
+        );
+        assert_pool_empty();
+
+        BUS.subscribe(&LOGGER, PRESSURE_SIG);
+        BUS.publish(test_arc::Arc::new(Reading::Pressure(970)));
+        assert_eq!(run_until_idle(&receiver), ["LOGGER_Pressure(970)"]);
+        assert_pool_empty();
+    }
+
+    // With QK, a subscriber preempting the publisher only runs once every subscriber has
+    // received the event, so that the events it publishes in turn are queued after it
+    mod qk {
+        use super::*;
+        use crate::kernel::qk::QkKernel;
+
+        #[derive(Debug)]
+        enum RelayEvt {
+            Reading(&'static Reading),
+        }
+
+        impl From<&'static Reading> for RelayEvt {
+            fn from(reading: &'static Reading) -> Self {
+                RelayEvt::Reading(reading)
+            }
+        }
+
+        // Log the readings received, and publish `relayed` on receiving a temperature
+        struct Relay {
+            name: &'static str,
+            relayed: Option<&'static Reading>,
+            sender: Sender<String>,
+        }
+
+        impl TopState for Relay {
+            type Evt = RelayEvt;
+
+            fn init(&mut self) -> InitResult<Self> {
+                init_transition!(Relaying)
+            }
+        }
+
+        #[state(super_state= Top)]
+        impl State<Relaying> for Relay {
+            fn handle(&mut self, evt: &RelayEvt) -> HandleResult<Self> {
+                match evt {
+                    RelayEvt::Reading(reading) => {
+                        self.sender
+                            .send(format!("{}_{:?}", self.name, reading))
+                            .unwrap();
+                        if let (Reading::Temperature(_), Some(relayed)) = (reading, self.relayed) {
+                            BUS.publish(relayed);
+                        }
+                    }
+                }
+                handled!()
+            }
+        }
+
+        fn relay(
+            name: &'static str,
+            relayed: Option<&'static Reading>,
+            sender: &Sender<String>,
+        ) -> Relay {
+            Relay {
+                name,
+                relayed,
+                sender: sender.clone(),
+            }
+        }
+
+        static KERNEL: QkKernel = QkKernel::new();
+        static BUS: EventBus<&'static Reading, NB_SIGNALS> = EventBus::new();
+        static STORAGE_LOGGER: EventQueueStorage<RelayEvt, 4> = EventQueueStorage::new();
+        static STORAGE_DISPLAY: EventQueueStorage<RelayEvt, 4> = EventQueueStorage::new();
+        static STORAGE_ALARM: EventQueueStorage<RelayEvt, 4> = EventQueueStorage::new();
+        static LOGGER: ActiveObject<Relay> = ActiveObject::new(&STORAGE_LOGGER, 1, &KERNEL);
+        static DISPLAY: ActiveObject<Relay> = ActiveObject::new(&STORAGE_DISPLAY, 2, &KERNEL);
+        static ALARM: ActiveObject<Relay> = ActiveObject::new(&STORAGE_ALARM, 3, &KERNEL);
+        static TEMPERATURE: Reading = Reading::Temperature(21);
+        static PRESSURE: Reading = Reading::Pressure(1013);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn publish_from_subscriber_test() {
+            let (sender, receiver) = channel();
+            LOGGER.start(relay("LOGGER", None, &sender));
+            DISPLAY.start(relay("DISPLAY", None, &sender));
+            ALARM.start(relay("ALARM", Some(&PRESSURE), &sender));
+            KERNEL.start();
+
+            BUS.subscribe(&LOGGER, TEMPERATURE_SIG);
+            BUS.subscribe(&LOGGER, PRESSURE_SIG);
+            BUS.subscribe(&DISPLAY, TEMPERATURE_SIG);
+            BUS.subscribe(&DISPLAY, PRESSURE_SIG);
+            BUS.subscribe(&ALARM, TEMPERATURE_SIG);
+
+            BUS.publish(&TEMPERATURE);
+            assert_eq!(
+                receiver.try_iter().collect::<Vec<_>>(),
+                [
+                    "ALARM_Temperature(21)",
+                    "DISPLAY_Temperature(21)",
+                    "DISPLAY_Pressure(1013)",
+                    "LOGGER_Temperature(21)",
+                    "LOGGER_Pressure(1013)",
+                ]
+            );
+            assert_eq!(KERNEL.get_active_priority(), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already used")]
+    fn duplicate_priority_subscriber_test() {
+        static BUS: EventBus<test_arc::Arc<Reading>, NB_SIGNALS> = EventBus::new();
+        static STORAGE: EventQueueStorage<MonitorEvt, 1> = EventQueueStorage::new();
+        static IMPOSTOR: ActiveObject<Monitor> = ActiveObject::new(&STORAGE, 1, &KERNEL);
+        BUS.subscribe(&LOGGER, TEMPERATURE_SIG);
+        BUS.subscribe(&IMPOSTOR, PRESSURE_SIG);
+    }
+}
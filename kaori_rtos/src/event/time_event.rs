@@ -0,0 +1,394 @@
+use crate::active_object::EventSink;
+use crate::port::{interrupt, CriticalSection, Mutex};
+use core::cell::Cell;
+
+// Interface through which a `TimeEventList` walks the time events linked to it
+pub(crate) trait TimeEventLink: Sync {
+    fn on_tick(&self, cs: &CriticalSection);
+    fn get_next(&self, cs: &CriticalSection) -> Option<&'static dyn TimeEventLink>;
+    fn set_next(&self, cs: &CriticalSection, next: Option<&'static dyn TimeEventLink>);
+}
+
+// Time events armed at least once, all of them being processed on every tick
+pub struct TimeEventList {
+    head: Mutex<Cell<Option<&'static dyn TimeEventLink>>>,
+}
+
+impl TimeEventList {
+    pub const fn new() -> TimeEventList {
+        TimeEventList {
+            head: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    fn link(&self, cs: &CriticalSection, time_event: &'static dyn TimeEventLink) {
+        let head = self.head.borrow(cs);
+        time_event.set_next(cs, head.get());
+        head.set(Some(time_event));
+    }
+
+    // Advance the time of one tick. Meant to be called periodically from the SysTick handler
+    // on Cortex-M, or from a tick thread on std.
+    pub fn tick(&self) {
+        interrupt::free(|cs| {
+            let mut time_event = self.head.borrow(cs).get();
+            while let Some(current) = time_event {
+                current.on_tick(cs);
+                time_event = current.get_next(cs);
+            }
+        })
+    }
+}
+
+impl Default for TimeEventList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TimeEventState {
+    // Ticks remaining before expiration, 0 when disarmed
+    counter: u32,
+    // Value the counter is reloaded with on expiration, 0 for a one-shot time event
+    interval: u32,
+    linked: bool,
+    // Expirations whose event did not fit in the event queue of the owner
+    nb_dropped: u32,
+    next: Option<&'static dyn TimeEventLink>,
+}
+
+// Timer posting a preconfigured event to its owner active object each time it expires.
+// Arming, disarming and expiring all happen within critical sections: once `disarm()` reports
+// the time event was armed, its event is guaranteed not to be posted.
+pub struct TimeEvent<E: 'static> {
+    evt: E,
+    owner: &'static dyn EventSink<E>,
+    list: &'static TimeEventList,
+    state: Mutex<Cell<TimeEventState>>,
+}
+
+impl<E: Clone + Send + Sync + 'static> TimeEvent<E> {
+    pub const fn new(
+        list: &'static TimeEventList,
+        owner: &'static dyn EventSink<E>,
+        evt: E,
+    ) -> TimeEvent<E> {
+        TimeEvent {
+            evt,
+            owner,
+            list,
+            state: Mutex::new(Cell::new(TimeEventState {
+                counter: 0,
+                interval: 0,
+                linked: false,
+                nb_dropped: 0,
+                next: None,
+            })),
+        }
+    }
+
+    // Arm the time event to expire in `ticks` ticks, then every `interval` ticks if not null
+    pub fn arm(&'static self, ticks: u32, interval: u32) {
+        assert!(
+            ticks > 0,
+            "Time event cannot be armed with a null number of ticks"
+        );
+        interrupt::free(|cs| {
+            let state_cell = self.state.borrow(cs);
+            let mut state = state_cell.get();
+            assert!(state.counter == 0, "Time event already armed");
+            state.counter = ticks;
+            state.interval = interval;
+            if !state.linked {
+                state.linked = true;
+                state_cell.set(state);
+                self.list.link(cs, self);
+            } else {
+                state_cell.set(state);
+            }
+        })
+    }
+
+    // Return `true` if the time event was armed. Otherwise its last event may still be queued.
+    pub fn disarm(&self) -> bool {
+        interrupt::free(|cs| {
+            let state_cell = self.state.borrow(cs);
+            let mut state = state_cell.get();
+            let was_armed = state.counter != 0;
+            state.counter = 0;
+            state_cell.set(state);
+            was_armed
+        })
+    }
+
+    // Restart the countdown from `ticks`, keeping the interval. Return `true` if it was armed.
+    pub fn rearm(&'static self, ticks: u32) -> bool {
+        assert!(
+            ticks > 0,
+            "Time event cannot be armed with a null number of ticks"
+        );
+        interrupt::free(|cs| {
+            let interval = self.state.borrow(cs).get().interval;
+            let was_armed = self.disarm();
+            self.arm(ticks, interval);
+            was_armed
+        })
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.get_remaining_ticks() != 0
+    }
+
+    pub fn get_remaining_ticks(&self) -> u32 {
+        interrupt::free(|cs| self.state.borrow(cs).get().counter)
+    }
+
+    // Number of expirations dropped because the event queue of the owner was full, an overflow
+    // not being allowed to panic from the tick interrupt
+    pub fn get_nb_dropped(&self) -> u32 {
+        interrupt::free(|cs| self.state.borrow(cs).get().nb_dropped)
+    }
+}
+
+impl<E: Clone + Send + Sync + 'static> TimeEventLink for TimeEvent<E> {
+    fn on_tick(&self, cs: &CriticalSection) {
+        let state_cell = self.state.borrow(cs);
+        let mut state = state_cell.get();
+        if state.counter == 0 {
+            return;
+        }
+        state.counter -= 1;
+        let expired = state.counter == 0;
+        if expired {
+            state.counter = state.interval;
+        }
+        if expired && !self.owner.post_from_isr_with_margin(self.evt.clone(), 0) {
+            state.nb_dropped = state.nb_dropped.saturating_add(1);
+        }
+        state_cell.set(state);
+    }
+
+    fn get_next(&self, cs: &CriticalSection) -> Option<&'static dyn TimeEventLink> {
+        self.state.borrow(cs).get().next
+    }
+
+    fn set_next(&self, cs: &CriticalSection, next: Option<&'static dyn TimeEventLink>) {
+        let state_cell = self.state.borrow(cs);
+        let mut state = state_cell.get();
+        state.next = next;
+        state_cell.set(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::active_object::ActiveObject;
+    use crate::event::event_queue::EventQueueStorage;
+    use crate::kernel::qv::QvKernel;
+    use kaori_hsm::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum TimerEvt {
+        Timeout,
+        Blink,
+    }
+
+    struct TimerRecorder {
+        sender: Sender<TimerEvt>,
+    }
+
+    impl TopState for TimerRecorder {
+        type Evt = TimerEvt;
+
+        fn init(&mut self) -> InitResult<Self> {
+            init_transition!(Recording)
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<Recording> for TimerRecorder {
+        fn handle(&mut self, evt: &TimerEvt) -> HandleResult<Self> {
+            self.sender.send(*evt).unwrap();
+            handled!()
+        }
+    }
+
+    fn run_until_idle(kernel: &QvKernel, receiver: &Receiver<TimerEvt>) -> Vec<TimerEvt> {
+        while kernel.step(|| {}) {}
+        receiver.try_iter().collect()
+    }
+
+    mod manual_tick {
+        use super::*;
+        static KERNEL: QvKernel = QvKernel::new();
+        static TIME_EVENT_LIST: TimeEventList = TimeEventList::new();
+        static STORAGE: EventQueueStorage<TimerEvt, 4> = EventQueueStorage::new();
+        static AO: ActiveObject<TimerRecorder> = ActiveObject::new(&STORAGE, 1, &KERNEL);
+        static TIMEOUT: TimeEvent<TimerEvt> =
+            TimeEvent::new(&TIME_EVENT_LIST, &AO, TimerEvt::Timeout);
+        static BLINK: TimeEvent<TimerEvt> = TimeEvent::new(&TIME_EVENT_LIST, &AO, TimerEvt::Blink);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn manual_tick_test() {
+            let (sender, receiver) = channel();
+            AO.start(TimerRecorder { sender });
+
+            // One-shot
+            TIMEOUT.arm(3, 0);
+            TIME_EVENT_LIST.tick();
+            TIME_EVENT_LIST.tick();
+            assert_eq!(TIMEOUT.get_remaining_ticks(), 1);
+            assert_eq!(run_until_idle(&KERNEL, &receiver), []);
+            TIME_EVENT_LIST.tick();
+            assert!(!TIMEOUT.is_armed());
+            assert_eq!(run_until_idle(&KERNEL, &receiver), [TimerEvt::Timeout]);
+            TIME_EVENT_LIST.tick();
+            assert_eq!(run_until_idle(&KERNEL, &receiver), []);
+            assert!(!TIMEOUT.disarm());
+
+            // Periodic, along with a one-shot disarmed before expiring
+            BLINK.arm(1, 2);
+            TIMEOUT.arm(4, 0);
+            let mut output = Vec::new();
+            for tick in 1..=6 {
+                TIME_EVENT_LIST.tick();
+                output.extend(run_until_idle(&KERNEL, &receiver));
+                if tick == 3 {
+                    assert_eq!(TIMEOUT.get_remaining_ticks(), 1);
+                    assert!(TIMEOUT.disarm());
+                }
+            }
+            assert_eq!(output, [TimerEvt::Blink; 3]);
+            assert!(BLINK.disarm());
+
+            // Rearming restarts the countdown
+            TIMEOUT.arm(2, 0);
+            TIME_EVENT_LIST.tick();
+            assert!(TIMEOUT.rearm(2));
+            TIME_EVENT_LIST.tick();
+            assert_eq!(run_until_idle(&KERNEL, &receiver), []);
+            TIME_EVENT_LIST.tick();
+            assert_eq!(run_until_idle(&KERNEL, &receiver), [TimerEvt::Timeout]);
+            assert!(!TIMEOUT.rearm(1));
+            TIME_EVENT_LIST.tick();
+            assert_eq!(run_until_idle(&KERNEL, &receiver), [TimerEvt::Timeout]);
+        }
+
+        #[test]
+        #[should_panic(expected = "already armed")]
+        fn double_arm_test() {
+            static DOUBLE_ARMED_LIST: TimeEventList = TimeEventList::new();
+            static DOUBLE_ARMED: TimeEvent<TimerEvt> =
+                TimeEvent::new(&DOUBLE_ARMED_LIST, &AO, TimerEvt::Timeout);
+            DOUBLE_ARMED.arm(10, 0);
+            DOUBLE_ARMED.arm(10, 0);
+        }
+    }
+
+    mod qk_tick {
+        use super::*;
+        use crate::kernel::qk::QkKernel;
+
+        static KERNEL: QkKernel = QkKernel::new();
+        static TIME_EVENT_LIST: TimeEventList = TimeEventList::new();
+        static STORAGE: EventQueueStorage<TimerEvt, 4> = EventQueueStorage::new();
+        static AO: ActiveObject<TimerRecorder> = ActiveObject::new(&STORAGE, 1, &KERNEL);
+        static TIMEOUT: TimeEvent<TimerEvt> =
+            TimeEvent::new(&TIME_EVENT_LIST, &AO, TimerEvt::Timeout);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn qk_tick_test() {
+            let (sender, receiver) = channel();
+            AO.start(TimerRecorder { sender });
+            KERNEL.start();
+
+            // The expiration activates the active object on return from the tick interrupt
+            TIMEOUT.arm(2, 0);
+            KERNEL.simulate_isr(|| TIME_EVENT_LIST.tick());
+            assert_eq!(receiver.try_iter().collect::<Vec<_>>(), []);
+            KERNEL.simulate_isr(|| {
+                TIME_EVENT_LIST.tick();
+                assert_eq!(receiver.try_iter().collect::<Vec<_>>(), []);
+            });
+            assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [TimerEvt::Timeout]);
+        }
+    }
+
+    mod overflow {
+        use super::*;
+        static KERNEL: QvKernel = QvKernel::new();
+        static TIME_EVENT_LIST: TimeEventList = TimeEventList::new();
+        const STORAGE_CAPACITY: usize = 2;
+        static STORAGE: EventQueueStorage<TimerEvt, STORAGE_CAPACITY> = EventQueueStorage::new();
+        static AO: ActiveObject<TimerRecorder> = ActiveObject::new(&STORAGE, 1, &KERNEL);
+        static BLINK: TimeEvent<TimerEvt> = TimeEvent::new(&TIME_EVENT_LIST, &AO, TimerEvt::Blink);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn overflow_test() {
+            let (sender, receiver) = channel();
+            AO.start(TimerRecorder { sender });
+
+            // The expirations which do not fit in the event queue are counted, not posted
+            const NB_TICKS: usize = 5;
+            BLINK.arm(1, 1);
+            for _ in 0..NB_TICKS {
+                TIME_EVENT_LIST.tick();
+            }
+            let nb_dropped = (NB_TICKS - STORAGE_CAPACITY) as u32;
+            assert_eq!(BLINK.get_nb_dropped(), nb_dropped);
+            assert!(BLINK.is_armed());
+            assert_eq!(
+                run_until_idle(&KERNEL, &receiver),
+                [TimerEvt::Blink; STORAGE_CAPACITY]
+            );
+
+            TIME_EVENT_LIST.tick();
+            assert_eq!(run_until_idle(&KERNEL, &receiver), [TimerEvt::Blink]);
+            assert_eq!(BLINK.get_nb_dropped(), nb_dropped);
+            assert!(BLINK.disarm());
+        }
+    }
+
+    mod tick_thread {
+        use super::*;
+        use crate::port::tick::TickThread;
+        use std::time::Duration;
+
+        static KERNEL: QvKernel = QvKernel::new();
+        static TIME_EVENT_LIST: TimeEventList = TimeEventList::new();
+        static STORAGE: EventQueueStorage<TimerEvt, 8> = EventQueueStorage::new();
+        static AO: ActiveObject<TimerRecorder> = ActiveObject::new(&STORAGE, 1, &KERNEL);
+        static BLINK: TimeEvent<TimerEvt> = TimeEvent::new(&TIME_EVENT_LIST, &AO, TimerEvt::Blink);
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn tick_thread_test() {
+            let (sender, receiver) = channel();
+            AO.start(TimerRecorder { sender });
+            let tick_thread =
+                TickThread::spawn(Duration::from_micros(100), || TIME_EVENT_LIST.tick());
+
+            for _ in 0..50 {
+                BLINK.arm(1, 1);
+                while receiver.try_iter().count() == 0 {
+                    KERNEL.step(std::thread::yield_now);
+                }
+
+                // A periodic time event is always armed, so no expiration can slip in after
+                // disarming it
+                assert!(BLINK.disarm());
+                while KERNEL.step(|| {}) {}
+                receiver.try_iter().for_each(drop);
+                std::thread::sleep(Duration::from_micros(500));
+                assert!(!KERNEL.step(|| {}));
+            }
+            tick_thread.stop();
+        }
+    }
+}
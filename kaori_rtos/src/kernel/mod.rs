@@ -0,0 +1,161 @@
+use crate::active_object::Priority;
+use crate::port::{interrupt, Mutex};
+use core::cell::Cell;
+use portable_atomic as atomic;
+
+pub(crate) mod qk;
+pub(crate) mod qv;
+
+// Active objects priorities range from 1 to MAX_ACTIVE_OBJECTS, 0 being the priority of the idle loop
+pub const MAX_ACTIVE_OBJECTS: usize = 32;
+
+// Type-erased interface the kernels use to run the active objects they schedule
+pub trait Dispatch: Sync {
+    fn get_priority(&self) -> Priority;
+    fn has_events(&self) -> bool;
+    /// # Safety
+    /// Must only be called by the kernel the active object is registered to.
+    unsafe fn dispatch_one(&self) -> bool;
+}
+
+// Interface through which active objects notify the kernel they are registered to
+pub trait Kernel: Sync {
+    fn register(&self, active_object: &'static dyn Dispatch);
+    fn on_post(&self, prio: Priority);
+    fn on_post_from_isr(&self, prio: Priority);
+
+    // Prevent active objects of priority lower or equal to `ceiling` from preempting the caller
+    // until the returned status is handed back to `unlock()`. Kernels which never preempt the
+    // caller have nothing to lock.
+    fn lock(&self, _ceiling: Priority) -> SchedulerLockStatus {
+        SchedulerLockStatus {
+            prev_lock_ceiling: 0,
+        }
+    }
+
+    fn unlock(&self, _status: SchedulerLockStatus) {}
+}
+
+// Scheduler lock state to hand back to `Kernel::unlock()`
+#[derive(Debug, PartialEq, Eq)]
+pub struct SchedulerLockStatus {
+    prev_lock_ceiling: Priority,
+}
+
+// Bitmap of the priorities of the active objects having events to process
+pub struct ReadySet {
+    inner: atomic::AtomicU32,
+}
+
+impl ReadySet {
+    pub const fn new() -> ReadySet {
+        ReadySet {
+            inner: atomic::AtomicU32::new(0),
+        }
+    }
+
+    const fn prio_mask(prio: Priority) -> u32 {
+        1 << (prio - 1)
+    }
+
+    pub fn insert(&self, prio: Priority) {
+        self.inner
+            .fetch_or(Self::prio_mask(prio), atomic::Ordering::SeqCst);
+    }
+
+    pub fn remove(&self, prio: Priority) {
+        self.inner
+            .fetch_and(!Self::prio_mask(prio), atomic::Ordering::SeqCst);
+    }
+
+    pub fn contains(&self, prio: Priority) -> bool {
+        self.inner.load(atomic::Ordering::SeqCst) & Self::prio_mask(prio) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.load(atomic::Ordering::SeqCst) == 0
+    }
+
+    // Highest priority of the set
+    pub fn find_max(&self) -> Option<Priority> {
+        let inner = self.inner.load(atomic::Ordering::SeqCst);
+        if inner == 0 {
+            None
+        } else {
+            Some((u32::BITS - inner.leading_zeros()) as Priority)
+        }
+    }
+}
+
+impl Default for ReadySet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Active objects registered to a kernel, indexed by priority
+pub(crate) struct ActiveObjectTable {
+    inner: [Mutex<Cell<Option<&'static dyn Dispatch>>>; MAX_ACTIVE_OBJECTS],
+}
+
+impl ActiveObjectTable {
+    pub(crate) const fn new() -> ActiveObjectTable {
+        ActiveObjectTable {
+            inner: [const { Mutex::new(Cell::new(None)) }; MAX_ACTIVE_OBJECTS],
+        }
+    }
+
+    pub(crate) fn register(&self, active_object: &'static dyn Dispatch) {
+        let prio = active_object.get_priority();
+        interrupt::free(|cs| {
+            let slot = self.inner[prio as usize - 1].borrow(cs);
+            assert!(
+                slot.get().is_none(),
+                "Priority {} already used by another active object",
+                prio
+            );
+            slot.set(Some(active_object));
+        });
+    }
+
+    pub(crate) fn get(&self, prio: Priority) -> &'static dyn Dispatch {
+        interrupt::free(|cs| self.inner[prio as usize - 1].borrow(cs).get())
+            .unwrap_or_else(|| panic!("No active object registered at priority {}", prio))
+    }
+}
+
+// Clear the ready bit of an active object that may have run out of events. The queue is
+// checked after clearing the bit so that an event posted in-between is never missed.
+pub(crate) fn update_ready_set(ready_set: &ReadySet, active_object: &dyn Dispatch) {
+    let prio = active_object.get_priority();
+    ready_set.remove(prio);
+    if active_object.has_events() {
+        ready_set.insert(prio);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_set_test_0() {
+        let ready_set = ReadySet::new();
+        assert!(ready_set.is_empty());
+        assert_eq!(ready_set.find_max(), None);
+
+        ready_set.insert(1);
+        ready_set.insert(MAX_ACTIVE_OBJECTS as Priority);
+        ready_set.insert(7);
+        assert!(ready_set.contains(7));
+        assert_eq!(ready_set.find_max(), Some(MAX_ACTIVE_OBJECTS as Priority));
+
+        ready_set.remove(MAX_ACTIVE_OBJECTS as Priority);
+        assert_eq!(ready_set.find_max(), Some(7));
+        ready_set.remove(7);
+        assert!(!ready_set.contains(7));
+        assert_eq!(ready_set.find_max(), Some(1));
+        ready_set.remove(1);
+        assert!(ready_set.is_empty());
+    }
+}
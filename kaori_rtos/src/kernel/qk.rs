@@ -0,0 +1,411 @@
+use super::{
+    update_ready_set, ActiveObjectTable, Dispatch, Kernel, ReadySet, SchedulerLockStatus,
+    MAX_ACTIVE_OBJECTS,
+};
+use crate::active_object::Priority;
+use crate::port::{interrupt, scheduler};
+use portable_atomic as atomic;
+
+// Priority the kernel runs at until it is started, preventing any activation during startup
+const NOT_STARTED_PRIO: Priority = MAX_ACTIVE_OBJECTS as Priority + 1;
+
+// Preemptive non-blocking kernel. All active objects share a single stack: posting to an
+// active object of higher priority than the running one activates it immediately, its
+// run-to-completion step nesting in the preempted one. Posts from interrupts pend the
+// scheduler instead, which the port services on return from the interrupt.
+pub struct QkKernel {
+    ready_set: ReadySet,
+    active_objects: ActiveObjectTable,
+    act_prio: atomic::AtomicU8,
+    lock_ceiling: atomic::AtomicU8,
+}
+
+impl QkKernel {
+    pub const fn new() -> QkKernel {
+        QkKernel {
+            ready_set: ReadySet::new(),
+            active_objects: ActiveObjectTable::new(),
+            act_prio: atomic::AtomicU8::new(NOT_STARTED_PRIO),
+            lock_ceiling: atomic::AtomicU8::new(0),
+        }
+    }
+
+    pub fn get_active_priority(&self) -> Priority {
+        self.act_prio.load(atomic::Ordering::SeqCst)
+    }
+
+    // Highest priority ready active object allowed to preempt the running one, if any
+    fn sched(&self) -> Option<Priority> {
+        let prio = self.ready_set.find_max()?;
+        let threshold = self
+            .act_prio
+            .load(atomic::Ordering::SeqCst)
+            .max(self.lock_ceiling.load(atomic::Ordering::SeqCst));
+        if prio > threshold {
+            Some(prio)
+        } else {
+            None
+        }
+    }
+
+    // Run the active objects preempting the running one until none is eligible anymore.
+    // The active priority is raised in the same critical section as the scheduling decision,
+    // so that an activation nested in-between cannot dispatch the same active object again.
+    fn activate(&self) {
+        let preempted_prio = self.act_prio.load(atomic::Ordering::SeqCst);
+        while let Some(prio) = interrupt::free(|_| {
+            let prio = self.sched()?;
+            self.act_prio.store(prio, atomic::Ordering::SeqCst);
+            Some(prio)
+        }) {
+            let active_object = self.active_objects.get(prio);
+            unsafe {
+                active_object.dispatch_one();
+            }
+            interrupt::free(|_| {
+                update_ready_set(&self.ready_set, active_object);
+                self.act_prio
+                    .store(preempted_prio, atomic::Ordering::SeqCst);
+            });
+        }
+    }
+
+    // Allow activations and run the active objects having received events during startup
+    pub fn start(&self) {
+        assert!(
+            self.act_prio.swap(0, atomic::Ordering::SeqCst) == NOT_STARTED_PRIO,
+            "Kernel already started"
+        );
+        self.activate();
+    }
+
+    pub fn run(&self, on_idle: fn()) -> ! {
+        self.start();
+        loop {
+            on_idle();
+        }
+    }
+
+    // Return-from-preemption hook, to be called by the port when servicing a pended scheduler
+    // request (PendSV handler on Cortex-M)
+    pub fn on_scheduler_pended(&self) {
+        self.activate();
+    }
+
+    // Prevent active objects of priority lower or equal to `ceiling` from preempting the caller
+    pub fn lock(&self, ceiling: Priority) -> SchedulerLockStatus {
+        let prev_lock_ceiling = self.lock_ceiling.load(atomic::Ordering::SeqCst);
+        if ceiling > prev_lock_ceiling {
+            self.lock_ceiling.store(ceiling, atomic::Ordering::SeqCst);
+        }
+        SchedulerLockStatus { prev_lock_ceiling }
+    }
+
+    pub fn unlock(&self, status: SchedulerLockStatus) {
+        self.lock_ceiling
+            .store(status.prev_lock_ceiling, atomic::Ordering::SeqCst);
+        if scheduler::in_isr() {
+            scheduler::pend();
+        } else {
+            self.activate();
+        }
+    }
+
+    // Run `isr` as if it were an interrupt handler preempting the calling context
+    #[cfg(not(target_os = "none"))]
+    pub fn simulate_isr<R, F: FnOnce() -> R>(&self, isr: F) -> R {
+        scheduler::isr_entry();
+        let ret = isr();
+        if scheduler::isr_exit() {
+            self.on_scheduler_pended();
+        }
+        ret
+    }
+}
+
+impl Default for QkKernel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kernel for QkKernel {
+    fn register(&self, active_object: &'static dyn Dispatch) {
+        self.active_objects.register(active_object);
+        update_ready_set(&self.ready_set, active_object);
+    }
+
+    fn on_post(&self, prio: Priority) {
+        if scheduler::in_isr() {
+            self.on_post_from_isr(prio);
+        } else {
+            self.ready_set.insert(prio);
+            self.activate();
+        }
+    }
+
+    fn on_post_from_isr(&self, prio: Priority) {
+        self.ready_set.insert(prio);
+        scheduler::pend();
+    }
+
+    fn lock(&self, ceiling: Priority) -> SchedulerLockStatus {
+        QkKernel::lock(self, ceiling)
+    }
+
+    fn unlock(&self, status: SchedulerLockStatus) {
+        QkKernel::unlock(self, status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::active_object::ActiveObject;
+    use crate::event::event_queue::EventQueueStorage;
+    use kaori_hsm::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    type TestActiveObject = ActiveObject<'static, Recorder>;
+
+    // Each event carries the script of actions the receiving active object must execute
+    #[derive(Clone, Copy)]
+    struct Script(&'static [Action]);
+
+    #[derive(Clone, Copy)]
+    enum Action {
+        Log(&'static str),
+        Post(&'static TestActiveObject, Script),
+        Isr(&'static [Action]),
+        Lock(Priority),
+        Unlock,
+    }
+
+    struct Recorder {
+        name: &'static str,
+        kernel: &'static QkKernel,
+        lock_status: Option<SchedulerLockStatus>,
+        sender: Sender<String>,
+    }
+
+    impl Recorder {
+        fn run_script(&mut self, actions: &[Action], in_isr: bool) {
+            for action in actions {
+                match action {
+                    Action::Log(msg) => {
+                        let prio = self.kernel.get_active_priority();
+                        self.sender
+                            .send(format!("{}@{}:{}", self.name, prio, msg))
+                            .unwrap();
+                    }
+                    Action::Post(active_object, script) => {
+                        if in_isr {
+                            active_object.post_from_isr(*script);
+                        } else {
+                            active_object.post(*script);
+                        }
+                    }
+                    Action::Isr(actions) => {
+                        let kernel = self.kernel;
+                        kernel.simulate_isr(|| self.run_script(actions, true));
+                    }
+                    Action::Lock(ceiling) => self.lock_status = Some(self.kernel.lock(*ceiling)),
+                    Action::Unlock => self.kernel.unlock(self.lock_status.take().unwrap()),
+                }
+            }
+        }
+    }
+
+    impl TopState for Recorder {
+        type Evt = Script;
+
+        fn init(&mut self) -> InitResult<Self> {
+            init_transition!(Running)
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<Running> for Recorder {
+        fn handle(&mut self, evt: &Script) -> HandleResult<Self> {
+            self.run_script(evt.0, false);
+            handled!()
+        }
+    }
+
+    fn assert_eq_output(receiver: &Receiver<String>, expectations: &[&str]) {
+        let output: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(output, expectations);
+    }
+
+    macro_rules! define_active_objects {
+        ($kernel:ident, $(($ao:ident, $storage:ident, $prio:expr)),*) => {
+            static $kernel: QkKernel = QkKernel::new();
+            $(
+                static $storage: EventQueueStorage<Script, 4> = EventQueueStorage::new();
+                static $ao: TestActiveObject = ActiveObject::new(&$storage, $prio, &$kernel);
+            )*
+        };
+    }
+
+    fn start(
+        kernel: &'static QkKernel,
+        active_objects: &[(&'static TestActiveObject, &'static str)],
+    ) -> Receiver<String> {
+        let (sender, receiver) = channel();
+        for (active_object, name) in active_objects {
+            active_object.start(Recorder {
+                name,
+                kernel,
+                lock_status: None,
+                sender: sender.clone(),
+            });
+        }
+        receiver
+    }
+
+    mod synchronous_preemption {
+        use super::*;
+        define_active_objects!(
+            KERNEL,
+            (AO_LOW, STORAGE_LOW, 1),
+            (AO_MID, STORAGE_MID, 2),
+            (AO_HIGH, STORAGE_HIGH, 3)
+        );
+
+        static LOW_SCRIPT: [Action; 5] = [
+            Action::Log("BEGIN"),
+            Action::Post(&AO_HIGH, Script(&[Action::Log("RTC")])),
+            Action::Post(&AO_MID, Script(&[Action::Log("RTC")])),
+            Action::Post(&AO_LOW, Script(&[Action::Log("NEXT")])),
+            Action::Log("END"),
+        ];
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn synchronous_preemption_test() {
+            let receiver = start(
+                &KERNEL,
+                &[(&AO_LOW, "LOW"), (&AO_MID, "MID"), (&AO_HIGH, "HIGH")],
+            );
+
+            // Nothing runs until the kernel is started
+            AO_LOW.post(Script(&LOW_SCRIPT));
+            assert_eq_output(&receiver, &[]);
+
+            KERNEL.start();
+            assert_eq_output(
+                &receiver,
+                &[
+                    "LOW@1:BEGIN",
+                    "HIGH@3:RTC",
+                    "MID@2:RTC",
+                    "LOW@1:END",
+                    "LOW@1:NEXT",
+                ],
+            );
+            assert_eq!(KERNEL.get_active_priority(), 0);
+        }
+    }
+
+    mod isr_preemption {
+        use super::*;
+        define_active_objects!(
+            KERNEL,
+            (AO_LOW, STORAGE_LOW, 1),
+            (AO_MID, STORAGE_MID, 2),
+            (AO_HIGH, STORAGE_HIGH, 3)
+        );
+
+        static NESTED_ISR_SCRIPT: [Action; 2] = [
+            Action::Post(&AO_HIGH, Script(&[Action::Log("RTC")])),
+            Action::Log("NESTED_ISR"),
+        ];
+
+        static MID_SCRIPT: [Action; 5] = [
+            Action::Log("BEGIN"),
+            Action::Isr(&[
+                Action::Post(&AO_LOW, Script(&[Action::Log("RTC")])),
+                Action::Isr(&NESTED_ISR_SCRIPT),
+                Action::Log("ISR"),
+            ]),
+            Action::Log("AFTER_ISR"),
+            // Lower priority than the running active object: no preemption on ISR return
+            Action::Isr(&[Action::Post(&AO_LOW, Script(&[Action::Log("RTC")]))]),
+            Action::Log("END"),
+        ];
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn isr_preemption_test() {
+            let receiver = start(
+                &KERNEL,
+                &[(&AO_LOW, "LOW"), (&AO_MID, "MID"), (&AO_HIGH, "HIGH")],
+            );
+            KERNEL.start();
+
+            AO_MID.post(Script(&MID_SCRIPT));
+            assert_eq_output(
+                &receiver,
+                &[
+                    "MID@2:BEGIN",
+                    "MID@2:NESTED_ISR",
+                    "MID@2:ISR",
+                    // Preemption occurs on return from the outermost ISR only
+                    "HIGH@3:RTC",
+                    "MID@2:AFTER_ISR",
+                    "MID@2:END",
+                    "LOW@1:RTC",
+                    "LOW@1:RTC",
+                ],
+            );
+
+            // Interrupt preempting the idle loop
+            KERNEL.simulate_isr(|| {
+                AO_LOW.post_from_isr(Script(&[Action::Log("FROM_IDLE")]));
+                AO_HIGH.post(Script(&[Action::Log("FROM_IDLE")]));
+            });
+            assert_eq_output(&receiver, &["HIGH@3:FROM_IDLE", "LOW@1:FROM_IDLE"]);
+        }
+    }
+
+    mod scheduler_lock {
+        use super::*;
+        define_active_objects!(
+            KERNEL,
+            (AO_LOW, STORAGE_LOW, 1),
+            (AO_MID, STORAGE_MID, 2),
+            (AO_HIGH, STORAGE_HIGH, 3)
+        );
+
+        static LOW_SCRIPT: [Action; 6] = [
+            Action::Lock(2),
+            Action::Post(&AO_MID, Script(&[Action::Log("RTC")])),
+            Action::Post(&AO_HIGH, Script(&[Action::Log("RTC")])),
+            Action::Log("LOCKED"),
+            Action::Unlock,
+            Action::Log("UNLOCKED"),
+        ];
+
+        #[test]
+        #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+        fn scheduler_lock_test() {
+            let receiver = start(
+                &KERNEL,
+                &[(&AO_LOW, "LOW"), (&AO_MID, "MID"), (&AO_HIGH, "HIGH")],
+            );
+            KERNEL.start();
+
+            AO_LOW.post(Script(&LOW_SCRIPT));
+            assert_eq_output(
+                &receiver,
+                &[
+                    // Above the lock ceiling, HIGH still preempts
+                    "HIGH@3:RTC",
+                    "LOW@1:LOCKED",
+                    "MID@2:RTC",
+                    "LOW@1:UNLOCKED",
+                ],
+            );
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use super::{update_ready_set, ActiveObjectTable, Dispatch, Kernel, ReadySet};
+use crate::active_object::Priority;
+use crate::port::interrupt;
+
+// Cooperative kernel. Active objects never preempt each other: the highest priority active
+// object having events to process is given one run-to-completion step at a time.
+pub struct QvKernel {
+    ready_set: ReadySet,
+    active_objects: ActiveObjectTable,
+}
+
+impl QvKernel {
+    pub const fn new() -> QvKernel {
+        QvKernel {
+            ready_set: ReadySet::new(),
+            active_objects: ActiveObjectTable::new(),
+        }
+    }
+
+    /// Run one step of the scheduler: dispatch one event to the highest priority ready active
+    /// object, or call `on_idle` if none is ready. Return `true` if an active object was ready,
+    /// even though the event posted to it may not be published yet. Such an active object stays
+    /// ready, its event being dispatched by a later step.
+    ///
+    /// `on_idle` is called within a critical section so that it can safely put the core to sleep
+    /// waiting for an interrupt, no event being missed in-between.
+    pub fn step<F: FnOnce()>(&self, on_idle: F) -> bool {
+        let prio = interrupt::free(|_| {
+            let prio = self.ready_set.find_max();
+            if prio.is_none() {
+                on_idle();
+            }
+            prio
+        });
+        if let Some(prio) = prio {
+            let active_object = self.active_objects.get(prio);
+            unsafe { active_object.dispatch_one() };
+            update_ready_set(&self.ready_set, active_object);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn run(&self, on_idle: fn()) -> ! {
+        loop {
+            self.step(on_idle);
+        }
+    }
+}
+
+impl Default for QvKernel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kernel for QvKernel {
+    fn register(&self, active_object: &'static dyn Dispatch) {
+        self.active_objects.register(active_object);
+        update_ready_set(&self.ready_set, active_object);
+    }
+
+    fn on_post(&self, prio: Priority) {
+        self.ready_set.insert(prio);
+    }
+
+    fn on_post_from_isr(&self, prio: Priority) {
+        self.ready_set.insert(prio);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::active_object::ActiveObject;
+    use crate::event::event_queue::EventQueueStorage;
+    use kaori_hsm::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    #[derive(Debug, Clone, Copy)]
+    enum CounterEvt {
+        Increment,
+        Forward(u32),
+    }
+
+    struct Counter {
+        name: &'static str,
+        count: u32,
+        forward_to: Option<&'static ActiveObject<'static, Counter>>,
+        sender: Sender<String>,
+    }
+
+    impl TopState for Counter {
+        type Evt = CounterEvt;
+
+        fn init(&mut self) -> InitResult<Self> {
+            init_transition!(Counting)
+        }
+    }
+
+    #[state(super_state= Top)]
+    impl State<Counting> for Counter {
+        fn handle(&mut self, evt: &CounterEvt) -> HandleResult<Self> {
+            match evt {
+                CounterEvt::Increment => {
+                    self.count += 1;
+                    self.sender
+                        .send(format!("{}_{}", self.name, self.count))
+                        .unwrap();
+                }
+                CounterEvt::Forward(n) => {
+                    self.sender
+                        .send(format!("{}_FWD_{}", self.name, n))
+                        .unwrap();
+                    if let Some(forward_to) = self.forward_to {
+                        forward_to.post(CounterEvt::Forward(n + 1));
+                    }
+                }
+            }
+            handled!()
+        }
+    }
+
+    fn counter(
+        name: &'static str,
+        forward_to: Option<&'static ActiveObject<'static, Counter>>,
+        sender: &Sender<String>,
+    ) -> Counter {
+        Counter {
+            name,
+            count: 0,
+            forward_to,
+            sender: sender.clone(),
+        }
+    }
+
+    fn assert_eq_output(receiver: &Receiver<String>, expectations: &[&str]) {
+        let output: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(output, expectations);
+    }
+
+    static KERNEL: QvKernel = QvKernel::new();
+    static STORAGE_LOW: EventQueueStorage<CounterEvt, 4> = EventQueueStorage::new();
+    static STORAGE_MID: EventQueueStorage<CounterEvt, 4> = EventQueueStorage::new();
+    static STORAGE_HIGH: EventQueueStorage<CounterEvt, 4> = EventQueueStorage::new();
+    static AO_LOW: ActiveObject<Counter> = ActiveObject::new(&STORAGE_LOW, 1, &KERNEL);
+    static AO_MID: ActiveObject<Counter> = ActiveObject::new(&STORAGE_MID, 5, &KERNEL);
+    static AO_HIGH: ActiveObject<Counter> = ActiveObject::new(&STORAGE_HIGH, 32, &KERNEL);
+
+    #[test]
+    #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+    fn qv_kernel_test_0() {
+        let (sender, receiver) = channel();
+        AO_LOW.start(counter("LOW", None, &sender));
+        AO_MID.start(counter("MID", Some(&AO_HIGH), &sender));
+        AO_HIGH.start(counter("HIGH", Some(&AO_LOW), &sender));
+
+        let mut nb_idle_calls = 0;
+        assert!(!KERNEL.step(|| nb_idle_calls += 1));
+        assert_eq!(nb_idle_calls, 1);
+
+        AO_LOW.post(CounterEvt::Increment);
+        AO_MID.post_from_isr(CounterEvt::Increment);
+        AO_LOW.post(CounterEvt::Increment);
+        AO_HIGH.post(CounterEvt::Increment);
+        AO_MID.post(CounterEvt::Forward(0));
+
+        while KERNEL.step(|| nb_idle_calls += 1) {}
+        assert_eq!(nb_idle_calls, 2);
+        assert_eq_output(
+            &receiver,
+            &[
+                "HIGH_1",
+                "MID_1",
+                "MID_FWD_0",
+                // Forwarded event makes HIGH the highest priority ready active object again
+                "HIGH_FWD_1",
+                "LOW_1",
+                "LOW_2",
+                "LOW_FWD_2",
+            ],
+        );
+
+        // An active object made ready by a post still in flight keeps the kernel stepping
+        KERNEL.on_post(AO_LOW.get_priority());
+        assert!(KERNEL.step(|| nb_idle_calls += 1));
+        assert!(!KERNEL.step(|| nb_idle_calls += 1));
+        assert_eq!(nb_idle_calls, 3);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "kaori-hsm has undefined behavior under Miri")]
+    #[should_panic(expected = "already used")]
+    fn qv_kernel_duplicate_priority_test() {
+        static KERNEL: QvKernel = QvKernel::new();
+        static STORAGE_0: EventQueueStorage<CounterEvt, 1> = EventQueueStorage::new();
+        static STORAGE_1: EventQueueStorage<CounterEvt, 1> = EventQueueStorage::new();
+        static AO_0: ActiveObject<Counter> = ActiveObject::new(&STORAGE_0, 3, &KERNEL);
+        static AO_1: ActiveObject<Counter> = ActiveObject::new(&STORAGE_1, 3, &KERNEL);
+        let (sender, _receiver) = channel();
+        AO_0.start(counter("AO_0", None, &sender));
+        AO_1.start(counter("AO_1", None, &sender));
+    }
+}
@@ -1,7 +1,8 @@
 #![cfg_attr(target_os = "none", no_std)]
 #![allow(dead_code)]
-#![recursion_limit="100000"]
+mod active_object;
 mod event;
+mod kernel;
 mod memory_allocation;
 mod sync;
 // #[cfg(
@@ -11,8 +12,6 @@ mod sync;
 //     )
 // )]
 
-
-
 // #[cfg(all(not(armv6m), not(armv8m_base)))]
 
 #[cfg(not(target_os = "none"))]
@@ -26,11 +25,10 @@ use cortex_m_port as port;
 #[cfg(target_os = "none")]
 mod cortex_m_port;
 
-// mod utils;
+mod utils;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
     #[test]
     fn it_works() {}
 }
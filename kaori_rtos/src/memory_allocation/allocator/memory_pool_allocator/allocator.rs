@@ -1,14 +1,21 @@
 use super::{
-    memory_pool::{types::MemPoolId, MemoryPool, SlotAllocError, SlotFreeingError, SlotPointer},
+    memory_pool::{
+        types::MemPoolId, MemoryPool, MemoryPoolStats, SlotAllocError, SlotFreeingError,
+        SlotPointer,
+    },
+    trace::{MemoryPoolTracer, NoTracer},
     MemoryAccessor,
 };
-use crate::memory_allocation::allocator::Allocator;
+use crate::memory_allocation::allocator::{Allocator, BlockingAllocator};
+use crate::sync::WaitList;
+use core::time::Duration;
 pub type AllocationResult = Result<SlotPointer, AllocationError>;
 #[derive(Debug, PartialEq, Eq)]
 pub enum AllocationError {
     NullAllocation,
     NoMemoryAvailable,
     NoSlotLargeEnough,
+    NoSlotAlignedEnough,
 }
 
 pub type FreeResult = Result<(), FreeError>;
@@ -16,15 +23,64 @@ pub type FreeResult = Result<(), FreeError>;
 pub enum FreeError {
     InvalidSlotIndex,
     InvalidMemoryPoolId,
+    SlotAlreadyFree,
+    StaleSlotPointer,
 }
 
-pub struct MemoryPoolAllocator<'a> {
-    memory_pool_array: &'a [&'a MemoryPool<'a>],
+// Declare a module holding a set of memory pools and the allocator serving them, from a list of
+// (slot size in bytes, number of slots) sorted by ascending slot size. Pool ids are assigned in
+// order and slot sizes are rounded up to the smallest slot alignment. Misconfigurations, such as
+// slot sizes not strictly ascending once rounded, fail the constant evaluation of the statics.
+// The module also holds the `Box` type allocating from the best fitting pool, so that
+// `define_arc!` can be invoked on it.
+#[macro_export]
+macro_rules! define_memory_pools {
+    ($pools_mod:ident, [$(($slot_size:expr, $nb_slots:expr)),+ $(,)?]) => {
+        mod $pools_mod {
+            // Lets the entries refer to the items of the invoking module
+            #[allow(unused_imports)]
+            use super::*;
+            use $crate::memory_allocation::allocator::memory_pool_allocator::{
+                get_words_per_pool, get_words_per_slot, MemPoolId, MemoryPool, MemoryPoolAllocator,
+                SlotPool,
+            };
+
+            pub const NB_MEMORY_POOLS: usize = <[&str]>::len(&[$(stringify!($slot_size)),+]);
+            const _: () = assert!(
+                NB_MEMORY_POOLS <= MemPoolId::MAX as usize + 1,
+                "Too many memory pools"
+            );
+
+            pub static MEMORY_POOLS: [&MemoryPool<'static>; NB_MEMORY_POOLS] =
+                $crate::define_memory_pools!(@pools [] 0; $(($slot_size, $nb_slots)),+);
+            pub static ALLOCATOR: MemoryPoolAllocator<'static> =
+                MemoryPoolAllocator::new(&MEMORY_POOLS);
+
+            $crate::define_box!(@handle ALLOCATOR: MemoryPoolAllocator);
+        }
+    };
+    (@pools [$($memory_pool:expr),*] $pool_id:expr;) => {
+        [$($memory_pool),*]
+    };
+    (@pools [$($memory_pool:expr),*] $pool_id:expr;
+        ($slot_size:expr, $nb_slots:expr) $(, ($next_slot_size:expr, $next_nb_slots:expr))*) => {
+        $crate::define_memory_pools!(@pools [$($memory_pool,)* {
+            const WORDS_PER_SLOT: usize = get_words_per_slot($slot_size as usize);
+            static SLOT_POOL: SlotPool<{ get_words_per_pool($nb_slots, WORDS_PER_SLOT) }> =
+                SlotPool::new(WORDS_PER_SLOT, ($pool_id) as MemPoolId);
+            static MEMORY_POOL: MemoryPool<'static> = MemoryPool::from(&SLOT_POOL);
+            &MEMORY_POOL
+        }] $pool_id + 1; $(($next_slot_size, $next_nb_slots)),*)
+    };
 }
 
-impl<'a> MemoryPoolAllocator<'a> {
+pub struct MemoryPoolAllocator<'a, Tracer: MemoryPoolTracer = NoTracer> {
+    memory_pool_array: &'a [&'a MemoryPool<'a, Tracer>],
+}
+
+impl<'a, Tracer: MemoryPoolTracer> MemoryPoolAllocator<'a, Tracer> {
     const fn check_memory_pools_order(
-        memory_pool_array: &[&MemoryPool<'a>],
+        memory_pool_array: &[&MemoryPool<'a, Tracer>],
         mut bigger_slot_size: usize,
         expected_mem_pool_id: MemPoolId,
     ) {
@@ -46,19 +102,51 @@ impl<'a> MemoryPoolAllocator<'a> {
         }
     }
 
-    pub const fn new(memory_pool_array: &'a [&'a MemoryPool<'a>]) -> MemoryPoolAllocator<'a> {
+    pub const fn new(
+        memory_pool_array: &'a [&'a MemoryPool<'a, Tracer>],
+    ) -> MemoryPoolAllocator<'a, Tracer> {
         assert!(
-            memory_pool_array.len() > 0,
+            !memory_pool_array.is_empty(),
             "At least one memory pool must be defined"
         );
         let bigger_slot_size = 0;
         Self::check_memory_pools_order(memory_pool_array, bigger_slot_size, 0);
-        return MemoryPoolAllocator { memory_pool_array };
+        MemoryPoolAllocator { memory_pool_array }
     }
 
-    fn get_slot_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, ()> {
+    pub fn get_pool_stats(&self, mem_pool_id: MemPoolId) -> MemoryPoolStats {
+        self.memory_pool_array[mem_pool_id as usize].get_stats()
+    }
+
+    // Stats of all the pools accumulated. A full pool counts as a failure even if the
+    // allocation has been served by a pool of larger slots.
+    pub fn get_stats(&self) -> MemoryPoolStats {
+        self.memory_pool_array
+            .iter()
+            .fold(MemoryPoolStats::default(), |stats, memory_pool| {
+                stats.merge(&memory_pool.get_stats())
+            })
+    }
+
+    pub fn get_memory_pool(&self, mem_pool_id: MemPoolId) -> Option<&MemoryPool<'a, Tracer>> {
+        self.memory_pool_array.get(mem_pool_id as usize).copied()
+    }
+
+    // Rebuild the slot pointer of an allocated slot from its address, the pool being found by
+    // address range
+    pub fn get_slot_pointer(&self, ptr: *const u8) -> Option<SlotPointer> {
+        self.memory_pool_array
+            .iter()
+            .find_map(|memory_pool| memory_pool.get_slot_pointer(ptr))
+    }
+
+    // The slot is looked up in the pool whose id is encoded in the slot pointer
+    pub fn get_slot_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, ()> {
         let memory_pool_id = slot_pointer.get_mem_pool_id();
-        self.memory_pool_array[memory_pool_id as usize].get_slot_mut(slot_pointer)
+        self.memory_pool_array
+            .get(memory_pool_id as usize)
+            .ok_or(())?
+            .get_slot_mut(slot_pointer)
     }
 
     fn allocate(&self, layout: core::alloc::Layout) -> AllocationResult {
@@ -70,16 +158,24 @@ impl<'a> MemoryPoolAllocator<'a> {
             return Err(AllocationError::NoSlotLargeEnough);
         }
 
+        if !self.memory_pool_array.iter().any(|memory_pool| {
+            memory_pool.get_slot_size() >= layout.size()
+                && memory_pool.get_slot_align() >= layout.align()
+        }) {
+            return Err(AllocationError::NoSlotAlignedEnough);
+        }
+
         for memory_pool in self.memory_pool_array.iter() {
             match memory_pool.allocate(layout) {
                 Result::Ok(slot_pointer) => return Ok(slot_pointer),
                 Result::Err(err) => match err {
                     SlotAllocError::SlotNotLargeEnough => continue,
+                    SlotAllocError::SlotNotAlignedEnough => continue,
                     SlotAllocError::PoolFull => continue,
                 },
             }
         }
-        return Err(AllocationError::NoMemoryAvailable);
+        Err(AllocationError::NoMemoryAvailable)
     }
 
     unsafe fn free(&self, slot_pointer: SlotPointer) -> FreeResult {
@@ -91,14 +187,50 @@ impl<'a> MemoryPoolAllocator<'a> {
 
         if let Err(err) = memory_pool.free(slot_pointer) {
             match err {
-                SlotFreeingError::SlotOutOfRange => return Err(FreeError::InvalidSlotIndex),
+                SlotFreeingError::SlotOutOfRange => Err(FreeError::InvalidSlotIndex),
+                SlotFreeingError::SlotAlreadyFree => Err(FreeError::SlotAlreadyFree),
+                SlotFreeingError::StaleSlotPointer => Err(FreeError::StaleSlotPointer),
             }
         } else {
             Ok(())
         }
     }
+
+    // Wait for a slot to be freed by another context while all the pools able to serve the
+    // layout are full
+    fn allocate_blocking(
+        &self,
+        layout: core::alloc::Layout,
+        timeout: Duration,
+    ) -> AllocationResult {
+        let wait_lists = self
+            .memory_pool_array
+            .iter()
+            .filter(|memory_pool| memory_pool.get_slot_size() >= layout.size())
+            .map(|memory_pool| memory_pool.get_wait_list());
+        WaitList::wait_any(wait_lists, timeout, || match self.allocate(layout) {
+            Err(AllocationError::NoMemoryAvailable) => None,
+            res => Some(res),
+        })
+        .unwrap_or(Err(AllocationError::NoMemoryAvailable))
+    }
+}
+
+impl<'a, Tracer: MemoryPoolTracer> BlockingAllocator<SlotPointer, AllocationError>
+    for MemoryPoolAllocator<'a, Tracer>
+{
+    fn allocate_blocking(
+        &self,
+        layout: core::alloc::Layout,
+        timeout: Duration,
+    ) -> Result<SlotPointer, AllocationError> {
+        Self::allocate_blocking(self, layout, timeout)
+    }
 }
-impl<'a> Allocator<SlotPointer, FreeError, AllocationError> for MemoryPoolAllocator<'a> {
+
+impl<'a, Tracer: MemoryPoolTracer> Allocator<SlotPointer, FreeError, AllocationError>
+    for MemoryPoolAllocator<'a, Tracer>
+{
     unsafe fn free(&self, slot_pointer: SlotPointer) -> Result<(), FreeError> {
         Self::free(self, slot_pointer)
     }
@@ -108,7 +240,7 @@ impl<'a> Allocator<SlotPointer, FreeError, AllocationError> for MemoryPoolAlloca
     }
 }
 
-impl<'a> MemoryAccessor<SlotPointer> for MemoryPoolAllocator<'a> {
+impl<'a, Tracer: MemoryPoolTracer> MemoryAccessor<SlotPointer> for MemoryPoolAllocator<'a, Tracer> {
     fn get_slot_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, ()> {
         self.get_slot_mut(slot_pointer).map_err(|_| ())
     }
@@ -116,7 +248,9 @@ impl<'a> MemoryAccessor<SlotPointer> for MemoryPoolAllocator<'a> {
 
 #[cfg(test)]
 pub(super) mod tests {
-    use super::super::memory_pool::{types::MemPoolId, SlotPool};
+    use super::super::memory_pool::{
+        get_words_per_pool, get_words_per_pool_aligned, types::MemPoolId, SlotPool,
+    };
     use super::*;
 
     // Single pool test
@@ -128,7 +262,8 @@ pub(super) mod tests {
         const POOL0_ID: MemPoolId = 0;
         const POOL0_WORDS_PER_SLOT: usize = 1;
         const POOL0_SLOTS_PER_POOL: usize = 2;
-        const POOL0_WORDS_PER_POOL: usize = POOL0_SLOTS_PER_POOL * POOL0_WORDS_PER_SLOT;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
             SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
         static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
@@ -150,16 +285,12 @@ pub(super) mod tests {
                     let res0 = res0.unwrap();
 
                     let struct0_0 = MEMORY_POOL_0.get_slot_transmute(&res0).unwrap();
-                    struct0_0.write(Struct0 {
-                        a: core::usize::MAX,
-                    });
+                    struct0_0.write(Struct0 { a: usize::MAX });
 
                     let res1 = ALLOCATOR_0.allocate(Layout::new::<Struct0>());
                     let res1 = res1.unwrap();
                     let struct0_1 = MEMORY_POOL_0.get_slot_transmute(&res1).unwrap();
-                    struct0_1.write(Struct0 {
-                        a: core::usize::MIN,
-                    });
+                    struct0_1.write(Struct0 { a: usize::MIN });
 
                     let res2 = ALLOCATOR_0.allocate(Layout::new::<Struct0>());
                     assert_eq!(res2, Err(AllocationError::NoMemoryAvailable));
@@ -167,12 +298,12 @@ pub(super) mod tests {
                     let res5 = ALLOCATOR_0.allocate(Layout::new::<Struct1>());
                     assert_eq!(res5, Err(AllocationError::NullAllocation));
 
-                    assert_eq!(struct0_0.assume_init_mut().a, core::usize::MAX);
-                    assert_eq!(struct0_1.assume_init_mut().a, core::usize::MIN);
+                    assert_eq!(struct0_0.assume_init_mut().a, usize::MAX);
+                    assert_eq!(struct0_1.assume_init_mut().a, usize::MIN);
 
                     let res1 = ALLOCATOR_0.free(res1);
                     assert_eq!(res1, Ok(()));
-                    assert_eq!(struct0_0.assume_init_mut().a, core::usize::MAX);
+                    assert_eq!(struct0_0.assume_init_mut().a, usize::MAX);
 
                     let res0 = ALLOCATOR_0.free(res0);
                     assert_eq!(res0, Ok(()));
@@ -181,6 +312,164 @@ pub(super) mod tests {
         }
     }
 
+    mod mem_pool_allocator_stats_test {
+        use super::*;
+        use core::alloc::Layout;
+
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 1;
+        const POOL0_SLOTS_PER_POOL: usize = 2;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL_0: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_0);
+
+        const POOL1_ID: MemPoolId = 1;
+        const POOL1_WORDS_PER_SLOT: usize = 3;
+        const POOL1_SLOTS_PER_POOL: usize = 1;
+        const POOL1_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL1_SLOTS_PER_POOL, POOL1_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL_1: SlotPool<POOL1_WORDS_PER_POOL> =
+            SlotPool::<POOL1_WORDS_PER_POOL>::new(POOL1_WORDS_PER_SLOT, POOL1_ID);
+        static MEMORY_POOL_1: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_1);
+
+        static MEMORY_POOL_ARRAY_0: [&MemoryPool; 2] = [&MEMORY_POOL_0, &MEMORY_POOL_1];
+        static ALLOCATOR_0: MemoryPoolAllocator = MemoryPoolAllocator::new(&MEMORY_POOL_ARRAY_0);
+
+        #[test]
+        fn mem_pool_allocator_stats_test() {
+            unsafe {
+                let layout = Layout::new::<usize>();
+                let slot_pointers: Vec<_> = (0..3)
+                    .map(|_| ALLOCATOR_0.allocate(layout).unwrap())
+                    .collect();
+                assert_eq!(
+                    ALLOCATOR_0.allocate(layout),
+                    Err(AllocationError::NoMemoryAvailable)
+                );
+                for slot_pointer in slot_pointers {
+                    ALLOCATOR_0.free(slot_pointer).unwrap();
+                }
+
+                // Pool 0 being full, the third allocation spilled over to pool 1
+                assert_eq!(
+                    ALLOCATOR_0.get_pool_stats(POOL0_ID),
+                    MemoryPoolStats {
+                        nb_slots: 2,
+                        nb_free_slots: 2,
+                        min_nb_free_slots: 0,
+                        nb_allocations: 2,
+                        nb_frees: 2,
+                        nb_allocation_failures: 2,
+                    }
+                );
+                assert_eq!(
+                    ALLOCATOR_0.get_stats(),
+                    MemoryPoolStats {
+                        nb_slots: 3,
+                        nb_free_slots: 3,
+                        min_nb_free_slots: 0,
+                        nb_allocations: 3,
+                        nb_frees: 3,
+                        nb_allocation_failures: 3,
+                    }
+                );
+            }
+        }
+    }
+
+    mod mem_pool_allocator_alignment_test {
+        use super::super::super::memory_pool::MAX_SLOT_ALIGN;
+        use super::*;
+        use core::alloc::Layout;
+
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 2;
+        const POOL0_SLOTS_PER_POOL: usize = 2;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL_0: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_0);
+
+        const POOL1_ID: MemPoolId = 1;
+        const POOL1_WORDS_PER_SLOT: usize = 2 * MAX_SLOT_ALIGN / core::mem::size_of::<usize>();
+        const POOL1_SLOTS_PER_POOL: usize = 2;
+        const POOL1_WORDS_PER_POOL: usize =
+            get_words_per_pool_aligned(POOL1_SLOTS_PER_POOL, POOL1_WORDS_PER_SLOT, MAX_SLOT_ALIGN);
+        static STATIC_MEMORY_POOL_1: SlotPool<POOL1_WORDS_PER_POOL> =
+            SlotPool::<POOL1_WORDS_PER_POOL>::new_aligned(
+                POOL1_WORDS_PER_SLOT,
+                POOL1_ID,
+                MAX_SLOT_ALIGN,
+            );
+        static MEMORY_POOL_1: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_1);
+
+        static MEMORY_POOL_ARRAY_0: [&MemoryPool; 2] = [&MEMORY_POOL_0, &MEMORY_POOL_1];
+        static ALLOCATOR_0: MemoryPoolAllocator = MemoryPoolAllocator::new(&MEMORY_POOL_ARRAY_0);
+
+        #[test]
+        fn mem_pool_allocator_alignment_test() {
+            unsafe {
+                // Small enough for pool 0, but only pool 1 is aligned enough
+                let layout = Layout::from_size_align(1, MAX_SLOT_ALIGN).unwrap();
+                let res0 = ALLOCATOR_0.allocate(layout).unwrap();
+                assert_eq!(res0.get_mem_pool_id(), POOL1_ID);
+                let slot = ALLOCATOR_0.get_slot_mut(&res0).unwrap();
+                assert!((slot as usize).is_multiple_of(MAX_SLOT_ALIGN));
+
+                let res1 = ALLOCATOR_0.allocate(Layout::new::<u8>()).unwrap();
+                assert_eq!(res1.get_mem_pool_id(), POOL0_ID);
+
+                assert_eq!(
+                    ALLOCATOR_0.allocate(Layout::from_size_align(1, 2 * MAX_SLOT_ALIGN).unwrap()),
+                    Err(AllocationError::NoSlotAlignedEnough)
+                );
+
+                ALLOCATOR_0.free(res0).unwrap();
+                ALLOCATOR_0.free(res1).unwrap();
+            }
+        }
+    }
+
+    mod define_memory_pools_test {
+        use core::alloc::Layout;
+
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+        define_memory_pools!(
+            test_pools,
+            [(1, 4), (2 * WORD_SIZE + 1, 2), (8 * WORD_SIZE, 1)]
+        );
+
+        #[test]
+        fn define_memory_pools_test() {
+            assert_eq!(test_pools::NB_MEMORY_POOLS, 3);
+            let expected = [(WORD_SIZE, 4), (3 * WORD_SIZE, 2), (8 * WORD_SIZE, 1)];
+            for (pool_id, (slot_size, nb_slots)) in expected.into_iter().enumerate() {
+                let memory_pool = test_pools::MEMORY_POOLS[pool_id];
+                assert_eq!(memory_pool.get_mem_pool_id() as usize, pool_id);
+                assert_eq!(memory_pool.get_slot_size(), slot_size);
+                assert_eq!(memory_pool.get_stats().nb_slots, nb_slots);
+            }
+
+            // The largest request fitting a pool is served by it, one more byte by the next one
+            let slot_size = test_pools::MEMORY_POOLS[1].get_slot_size();
+            unsafe {
+                let res0 = test_pools::ALLOCATOR
+                    .allocate(Layout::from_size_align(slot_size, WORD_SIZE).unwrap())
+                    .unwrap();
+                assert_eq!(res0.get_mem_pool_id(), 1);
+                let res1 = test_pools::ALLOCATOR
+                    .allocate(Layout::from_size_align(slot_size + 1, WORD_SIZE).unwrap())
+                    .unwrap();
+                assert_eq!(res1.get_mem_pool_id(), 2);
+                test_pools::ALLOCATOR.free(res0).unwrap();
+                test_pools::ALLOCATOR.free(res1).unwrap();
+            }
+        }
+    }
+
     mod single_thread_randomized {
 
         use crate::memory_allocation::allocator::memory_pool_allocator::memory_pool::tests::PoolTestParams;
@@ -189,7 +478,8 @@ pub(super) mod tests {
         const POOL0_ID: MemPoolId = 0;
         const POOL0_WORDS_PER_SLOT: usize = 1;
         const POOL0_SLOTS_PER_POOL: usize = 10;
-        const POOL0_WORDS_PER_POOL: usize = POOL0_SLOTS_PER_POOL * POOL0_WORDS_PER_SLOT;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_0: SlotPool<POOL0_WORDS_PER_POOL> =
             SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
         static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_0);
@@ -197,7 +487,8 @@ pub(super) mod tests {
         const POOL1_ID: MemPoolId = 1;
         const POOL1_WORDS_PER_SLOT: usize = 3;
         const POOL1_SLOTS_PER_POOL: usize = 8;
-        const POOL1_WORDS_PER_POOL: usize = POOL1_SLOTS_PER_POOL * POOL1_WORDS_PER_SLOT;
+        const POOL1_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL1_SLOTS_PER_POOL, POOL1_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_1: SlotPool<POOL1_WORDS_PER_POOL> =
             SlotPool::<POOL1_WORDS_PER_POOL>::new(POOL1_WORDS_PER_SLOT, POOL1_ID);
         static MEMORY_POOL_1: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_1);
@@ -205,7 +496,8 @@ pub(super) mod tests {
         const POOL2_ID: MemPoolId = 2;
         const POOL2_WORDS_PER_SLOT: usize = 8;
         const POOL2_SLOTS_PER_POOL: usize = 4;
-        const POOL2_WORDS_PER_POOL: usize = POOL2_SLOTS_PER_POOL * POOL2_WORDS_PER_SLOT;
+        const POOL2_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL2_SLOTS_PER_POOL, POOL2_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_2: SlotPool<POOL2_WORDS_PER_POOL> =
             SlotPool::<POOL2_WORDS_PER_POOL>::new(POOL2_WORDS_PER_SLOT, POOL2_ID);
         static MEMORY_POOL_2: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_2);
@@ -236,7 +528,7 @@ pub(super) mod tests {
 
             let test_params = TestParams {
                 pool_test_params: &pool_test_params_0,
-                n_iterations: 10000,
+                n_iterations: if cfg!(miri) { 200 } else { 10000 },
             };
 
             let mut tester = Tester::new(&ALLOCATOR_0);
@@ -254,7 +546,8 @@ pub(super) mod tests {
         const POOL0_ID: MemPoolId = 0;
         const POOL0_WORDS_PER_SLOT: usize = 1;
         const POOL0_SLOTS_PER_POOL: usize = 10;
-        const POOL0_WORDS_PER_POOL: usize = POOL0_SLOTS_PER_POOL * POOL0_WORDS_PER_SLOT;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_0: SlotPool<POOL0_WORDS_PER_POOL> =
             SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
         static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_0);
@@ -262,7 +555,8 @@ pub(super) mod tests {
         const POOL1_ID: MemPoolId = 1;
         const POOL1_WORDS_PER_SLOT: usize = 3;
         const POOL1_SLOTS_PER_POOL: usize = 8;
-        const POOL1_WORDS_PER_POOL: usize = POOL1_SLOTS_PER_POOL * POOL1_WORDS_PER_SLOT;
+        const POOL1_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL1_SLOTS_PER_POOL, POOL1_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_1: SlotPool<POOL1_WORDS_PER_POOL> =
             SlotPool::<POOL1_WORDS_PER_POOL>::new(POOL1_WORDS_PER_SLOT, POOL1_ID);
         static MEMORY_POOL_1: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_1);
@@ -270,7 +564,8 @@ pub(super) mod tests {
         const POOL2_ID: MemPoolId = 2;
         const POOL2_WORDS_PER_SLOT: usize = 8;
         const POOL2_SLOTS_PER_POOL: usize = 4;
-        const POOL2_WORDS_PER_POOL: usize = POOL2_SLOTS_PER_POOL * POOL2_WORDS_PER_SLOT;
+        const POOL2_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL2_SLOTS_PER_POOL, POOL2_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL_2: SlotPool<POOL2_WORDS_PER_POOL> =
             SlotPool::<POOL2_WORDS_PER_POOL>::new(POOL2_WORDS_PER_SLOT, POOL2_ID);
         static MEMORY_POOL_2: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL_2);
@@ -281,7 +576,10 @@ pub(super) mod tests {
         use super::super::super::memory_pool::tests::{TestParams, Tester};
 
         const NB_THREADS: usize = 2;
+        // The lock-free free list may read the link of a slot popped and written by another
+        // thread, which the CAS recovers from but Miri reports as a data race
         #[test]
+        #[cfg_attr(miri, ignore)]
         fn multi_thread_randomized() {
             let mut join_handle_vec = Vec::new();
             for _ in 0..NB_THREADS {
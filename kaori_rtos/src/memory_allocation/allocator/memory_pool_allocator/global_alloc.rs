@@ -0,0 +1,189 @@
+use super::{
+    allocator::MemoryPoolAllocator,
+    trace::{MemoryPoolTracer, NoTracer},
+};
+use crate::memory_allocation::allocator::Allocator;
+use core::alloc::{GlobalAlloc, Layout};
+
+// Adapter serving the `alloc` collections from memory pools, to be registered with
+// `#[global_allocator]`. Allocation failures return a null pointer, so that they end up in
+// `handle_alloc_error()` and the `alloc_error_handler`.
+pub struct GlobalMemoryPoolAllocator<Tracer: MemoryPoolTracer + 'static = NoTracer> {
+    allocator: &'static MemoryPoolAllocator<'static, Tracer>,
+}
+
+impl<Tracer: MemoryPoolTracer> GlobalMemoryPoolAllocator<Tracer> {
+    pub const fn new(
+        allocator: &'static MemoryPoolAllocator<'static, Tracer>,
+    ) -> GlobalMemoryPoolAllocator<Tracer> {
+        GlobalMemoryPoolAllocator { allocator }
+    }
+
+    pub const fn get_allocator(&self) -> &'static MemoryPoolAllocator<'static, Tracer> {
+        self.allocator
+    }
+
+    fn get_slot_size(&self, ptr: *const u8) -> Option<usize> {
+        let slot_pointer = self.allocator.get_slot_pointer(ptr)?;
+        self.allocator
+            .get_memory_pool(slot_pointer.get_mem_pool_id())
+            .map(|memory_pool| memory_pool.get_slot_size())
+    }
+}
+
+unsafe impl<Tracer: MemoryPoolTracer> GlobalAlloc for GlobalMemoryPoolAllocator<Tracer> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Allocator::allocate(self.allocator, layout)
+            .ok()
+            .and_then(|slot_pointer| self.allocator.get_slot_mut(&slot_pointer).ok())
+            .unwrap_or(core::ptr::null_mut())
+    }
+
+    // Pointers not allocated from the pools are left alone, as a failed deallocation cannot
+    // be reported
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        let freed = self
+            .allocator
+            .get_slot_pointer(ptr)
+            .map(|slot_pointer| Allocator::free(self.allocator, slot_pointer));
+        debug_assert!(
+            matches!(freed, Some(Ok(()))),
+            "Deallocation of {:?} failed: {:?}",
+            ptr,
+            freed
+        );
+    }
+
+    // The memory stays in place as long as its slot is large enough, otherwise it is moved to
+    // the smallest pool able to hold the new size
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let Some(slot_size) = self.get_slot_size(ptr) else {
+            return core::ptr::null_mut();
+        };
+        if new_size <= slot_size {
+            return ptr;
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size());
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_memory_pools;
+    use crate::memory_allocation::allocator::memory_pool_allocator::{
+        get_words_per_pool, MemoryPool, MemoryPoolAllocator, SlotPool,
+    };
+
+    const WORD_SIZE: usize = core::mem::size_of::<usize>();
+
+    fn fill(ptr: *mut u8, len: usize) {
+        for i in 0..len {
+            unsafe { ptr.add(i).write(i as u8) };
+        }
+    }
+
+    fn is_filled(ptr: *const u8, len: usize) -> bool {
+        (0..len).all(|i| unsafe { ptr.add(i).read() } == i as u8)
+    }
+
+    mod unchecked {
+        use super::*;
+
+        define_memory_pools!(
+            global_pools,
+            [(2 * WORD_SIZE, 2), (8 * WORD_SIZE, 1), (32 * WORD_SIZE, 1)]
+        );
+        static GLOBAL: GlobalMemoryPoolAllocator =
+            GlobalMemoryPoolAllocator::new(&global_pools::ALLOCATOR);
+
+        #[test]
+        fn global_alloc_test() {
+            unsafe {
+                let small_layout = Layout::new::<[usize; 2]>();
+                let ptr0 = GLOBAL.alloc(small_layout);
+                let ptr1 = GLOBAL.alloc(small_layout);
+                assert!(!ptr0.is_null() && !ptr1.is_null());
+                assert_eq!(global_pools::ALLOCATOR.get_pool_stats(0).nb_free_slots, 0);
+
+                // Growing within the slot keeps the memory in place
+                fill(ptr0, 2 * WORD_SIZE);
+                assert_eq!(
+                    GLOBAL.realloc(ptr0, Layout::new::<usize>(), 2 * WORD_SIZE),
+                    ptr0
+                );
+
+                // Growing beyond moves the content to the next size class
+                let ptr2 = GLOBAL.realloc(ptr0, small_layout, 5 * WORD_SIZE);
+                assert_ne!(ptr2, ptr0);
+                assert!(is_filled(ptr2, 2 * WORD_SIZE));
+                assert_eq!(global_pools::ALLOCATOR.get_pool_stats(0).nb_free_slots, 1);
+                assert_eq!(global_pools::ALLOCATOR.get_pool_stats(1).nb_free_slots, 0);
+
+                // Failures are reported with a null pointer, the memory being left untouched
+                let large_layout = Layout::from_size_align(8 * WORD_SIZE, WORD_SIZE).unwrap();
+                let ptr3 = GLOBAL.realloc(ptr2, large_layout, 64 * WORD_SIZE);
+                assert!(ptr3.is_null());
+                let ptr3 = GLOBAL.realloc(ptr2, large_layout, 32 * WORD_SIZE);
+                assert!(!ptr3.is_null());
+                assert!(is_filled(ptr3, 2 * WORD_SIZE));
+                assert!(GLOBAL.alloc(Layout::new::<[usize; 32]>()).is_null());
+
+                GLOBAL.dealloc(ptr1, small_layout);
+                GLOBAL.dealloc(ptr3, Layout::new::<[usize; 32]>());
+                assert_eq!(global_pools::ALLOCATOR.get_stats().nb_free_slots, 4);
+
+                // The pointer rebuilt from the address of a slot is the allocated one
+                let slot_pointer = global_pools::ALLOCATOR.allocate(small_layout).unwrap();
+                let ptr4 = global_pools::ALLOCATOR.get_slot_mut(&slot_pointer).unwrap();
+                assert_eq!(
+                    global_pools::ALLOCATOR.get_slot_pointer(ptr4),
+                    Some(slot_pointer)
+                );
+                global_pools::ALLOCATOR.free(slot_pointer).unwrap();
+            }
+        }
+    }
+
+    mod checked {
+        use super::*;
+
+        static CHECKED_SLOT_POOL: SlotPool<{ get_words_per_pool(4, 2) }, 4> = SlotPool::new(2, 0);
+        static CHECKED_MEMORY_POOL: MemoryPool = MemoryPool::from(&CHECKED_SLOT_POOL);
+        static CHECKED_MEMORY_POOLS: [&MemoryPool; 1] = [&CHECKED_MEMORY_POOL];
+        static CHECKED_ALLOCATOR: MemoryPoolAllocator =
+            MemoryPoolAllocator::new(&CHECKED_MEMORY_POOLS);
+        static GLOBAL: GlobalMemoryPoolAllocator =
+            GlobalMemoryPoolAllocator::new(&CHECKED_ALLOCATOR);
+
+        #[test]
+        fn checked_global_alloc_test() {
+            unsafe {
+                let layout = Layout::new::<usize>();
+                for _ in 0..3 {
+                    let ptr = GLOBAL.alloc(layout);
+                    let slot_pointer = CHECKED_ALLOCATOR.allocate(layout).unwrap();
+
+                    // The live pointer is recovered, tag included
+                    let slot_ptr = CHECKED_ALLOCATOR.get_slot_mut(&slot_pointer).unwrap();
+                    assert_eq!(
+                        CHECKED_ALLOCATOR.get_slot_pointer(slot_ptr),
+                        Some(slot_pointer)
+                    );
+                    assert_eq!(CHECKED_ALLOCATOR.get_slot_pointer(slot_ptr.add(1)), None);
+
+                    GLOBAL.dealloc(ptr, layout);
+                    assert_eq!(CHECKED_ALLOCATOR.get_slot_pointer(ptr), None);
+                    CHECKED_ALLOCATOR.free(slot_pointer).unwrap();
+                }
+                assert_eq!(CHECKED_ALLOCATOR.get_stats().nb_free_slots, 4);
+            }
+        }
+    }
+}
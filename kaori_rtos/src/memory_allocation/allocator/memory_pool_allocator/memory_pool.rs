@@ -1,104 +1,94 @@
-use crate::memory_allocation::allocator::Allocator;
+#[cfg(feature = "slot-poisoning")]
+use crate::memory_allocation::allocator::memory_pool_allocator::poison::{
+    self, FaultHandler, MemoryPoolFault, MemoryPoolFaultKind,
+};
+use crate::memory_allocation::allocator::memory_pool_allocator::trace::{
+    MemoryPoolTracer, NoTracer,
+};
 use crate::memory_allocation::allocator::memory_pool_allocator::MemoryAccessor;
-use crate::sync::{AsyncArrayCell, AsyncArrayCellRef};
+use crate::memory_allocation::allocator::{Allocator, BlockingAllocator};
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+use crate::port::{interrupt, Mutex};
+use crate::sync::{AsyncArrayCell, AsyncArrayCellRef, WaitList};
+#[cfg(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr"))]
+use crate::utils::atomic as utils_atomic;
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+use core::cell::Cell;
 use core::mem::MaybeUninit;
+use core::ptr::NonNull;
 use core::result::Result;
+use core::time::Duration;
+use free_list::{FreeList, FreeSlot};
 use portable_atomic as atomic;
-struct AtomicSlotPointer {
-    inner: atomic::AtomicUsize,
-}
+
+mod free_list;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct SlotPointer {
-    inner: usize,
+    inner: RawSlotPointer,
 }
 
-#[cfg(target_pointer_width = "32")]
+// Bit layout of the slot pointers, from the most significant bits: pool id, tag, slot index.
+// The 64-bit layout is the native one on 64-bit targets, and can be selected on 32-bit targets
+// with the "wide-slot-pointer" feature for larger pools and a slower wrapping tag.
 pub mod types {
-    pub type SlotIndex = u16;
-    pub type MemPoolId = u8;
-    pub type SlotTag = u16;
-    pub const MP_ID_SH: usize = 24;
-    pub const MP_ID_MSK: usize = 0xFF000000;
-    pub const MP_TAG_SH: usize = 12;
-    pub const MP_TAG_MSK: usize = 0x00FFF000;
-    pub const MP_TAG_MAX_VALUE: SlotTag = (MP_TAG_MSK >> MP_TAG_SH) as u32;
-    pub const MP_TAG_MIN_VALUE: SlotTag = 0;
-    pub const MP_SLOT_IDX_SH: usize = 0;
-    pub const MP_SLOT_IDX_MSK: usize = 0x00000FFF;
-    pub const MP_SLOT_IDX_MAX_VAL: SlotIndex = MP_SLOT_IDX_MSK as u32 - 1;
-    pub const MP_SLOT_IDX_MIN_VAL: SlotIndex = 0;
-    pub const MP_SLOT_IDX_NEXT_NONE: SlotIndex = MP_SLOT_IDX_MAX_VAL + 1;
-}
+    #[cfg(not(any(target_pointer_width = "64", feature = "wide-slot-pointer")))]
+    mod layout {
+        pub type RawSlotPointer = u32;
+        pub type AtomicRawSlotPointer = portable_atomic::AtomicU32;
+        pub type SlotIndex = u16;
+        pub type SlotTag = u16;
+        pub const MP_TAG_BITS: usize = 12;
+        pub const MP_SLOT_IDX_BITS: usize = 12;
+    }
 
-#[cfg(target_pointer_width = "64")]
-pub mod types {
-    pub type SlotIndex = u32;
+    #[cfg(any(target_pointer_width = "64", feature = "wide-slot-pointer"))]
+    mod layout {
+        pub type RawSlotPointer = u64;
+        pub type AtomicRawSlotPointer = portable_atomic::AtomicU64;
+        pub type SlotIndex = u32;
+        pub type SlotTag = u32;
+        pub const MP_TAG_BITS: usize = 36;
+        pub const MP_SLOT_IDX_BITS: usize = 20;
+    }
+
+    pub use layout::*;
     pub type MemPoolId = u8;
-    pub type SlotTag = u32;
-    pub const MP_ID_SH: usize = 56;
-    pub const MP_ID_MSK: usize = 0xff00000000000000;
-    pub const MP_TAG_SH: usize = 20;
-    pub const MP_TAG_MSK: usize = 0x00fffffffff00000;
-    pub const MP_TAG_MAX_VALUE: SlotTag = (MP_TAG_MSK >> MP_TAG_SH) as u32;
-    pub const MP_TAG_MIN_VALUE: SlotTag = 0;
     pub const MP_SLOT_IDX_SH: usize = 0;
-    pub const MP_SLOT_IDX_MSK: usize = 0x00000000000fffff;
-    pub const MP_SLOT_IDX_MAX_VAL: SlotIndex = MP_SLOT_IDX_MSK as u32 - 1;
+    pub const MP_SLOT_IDX_MSK: RawSlotPointer = ((1 << MP_SLOT_IDX_BITS) - 1) << MP_SLOT_IDX_SH;
+    pub const MP_TAG_SH: usize = MP_SLOT_IDX_SH + MP_SLOT_IDX_BITS;
+    pub const MP_TAG_MSK: RawSlotPointer = ((1 << MP_TAG_BITS) - 1) << MP_TAG_SH;
+    pub const MP_ID_SH: usize = MP_TAG_SH + MP_TAG_BITS;
+    pub const MP_ID_MSK: RawSlotPointer = (MemPoolId::MAX as RawSlotPointer) << MP_ID_SH;
+    // Tags wrap around at the largest value both the tag field and SlotTag can hold
+    pub const MP_TAG_MAX_VALUE: SlotTag = (MP_TAG_MSK >> MP_TAG_SH) as SlotTag;
+    pub const MP_TAG_MIN_VALUE: SlotTag = 0;
+    pub const MP_SLOT_IDX_MAX_VAL: SlotIndex = (MP_SLOT_IDX_MSK >> MP_SLOT_IDX_SH) as SlotIndex - 1;
     pub const MP_SLOT_IDX_MIN_VAL: SlotIndex = 0;
     pub const MP_SLOT_IDX_NEXT_NONE: SlotIndex = MP_SLOT_IDX_MAX_VAL + 1;
-}
-
-use types::*;
 
-impl AtomicSlotPointer {
-    const fn new(pool_id: MemPoolId, index: Option<SlotIndex>) -> AtomicSlotPointer {
-        let new_index;
-        if let Some(index) = index {
-            new_index = index;
-        } else {
-            new_index = MP_SLOT_IDX_NEXT_NONE;
-        }
-        let slot_index = ((new_index as usize) << MP_SLOT_IDX_SH) & MP_SLOT_IDX_MSK;
-        let mem_pool_id = ((pool_id as usize) << MP_ID_SH) & MP_ID_MSK;
-        let slot_pointer_val = slot_index | mem_pool_id;
-
-        AtomicSlotPointer {
-            inner: atomic::AtomicUsize::new(slot_pointer_val),
-        }
-    }
+    // Words taken by the free list link at the start of each free slot
+    pub const LINK_WORDS: usize =
+        core::mem::size_of::<super::FreeSlot>().div_ceil(core::mem::size_of::<usize>());
 
-    const fn from(slot_pointer: SlotPointer) -> AtomicSlotPointer {
-        AtomicSlotPointer {
-            inner: atomic::AtomicUsize::new(slot_pointer.inner as usize),
-        }
-    }
+    const _: () = assert!(MP_ID_SH + MemPoolId::BITS as usize == RawSlotPointer::BITS as usize);
+    const _: () = assert!(MP_SLOT_IDX_BITS <= SlotIndex::BITS as usize);
+}
 
-    fn compare_exchange_weak(
-        &self,
-        current: SlotPointer,
-        new: SlotPointer,
-        success: atomic::Ordering,
-        failure: atomic::Ordering,
-    ) -> Result<usize, usize> {
-        self.inner
-            .compare_exchange_weak(current.inner, new.inner, success, failure)
-    }
+use types::*;
 
-    fn load(&self, ordering: atomic::Ordering) -> SlotPointer {
+impl SlotPointer {
+    const fn from(raw_slot_pointer: RawSlotPointer) -> SlotPointer {
         SlotPointer {
-            inner: self.inner.load(ordering),
+            inner: raw_slot_pointer,
         }
     }
 
-    fn store(&self, slot_pointer: SlotPointer, ordering: atomic::Ordering) {
-        self.inner.store(slot_pointer.inner, ordering);
-    }
-}
-impl SlotPointer {
-    const fn from(raw_slot_pointer: usize) -> SlotPointer {
+    const fn new(mem_pool_id: MemPoolId, index: SlotIndex, tag: RawSlotPointer) -> SlotPointer {
         SlotPointer {
-            inner: raw_slot_pointer,
+            inner: ((mem_pool_id as RawSlotPointer) << MP_ID_SH)
+                | ((tag << MP_TAG_SH) & MP_TAG_MSK)
+                | (((index as RawSlotPointer) << MP_SLOT_IDX_SH) & MP_SLOT_IDX_MSK),
         }
     }
 
@@ -106,21 +96,6 @@ impl SlotPointer {
         ((self.inner & MP_ID_MSK) >> MP_ID_SH) as MemPoolId
     }
 
-    fn increment_tag(&mut self) {
-        let mem_pool_id = ((self.inner & MP_ID_MSK) >> MP_ID_SH) as MemPoolId;
-        let mut tag = ((self.inner & MP_TAG_MSK) >> MP_TAG_SH) as SlotTag;
-        let index = ((self.inner & MP_SLOT_IDX_MSK) >> MP_SLOT_IDX_SH) as SlotIndex;
-        if tag == MP_TAG_MAX_VALUE {
-            tag = MP_TAG_MIN_VALUE;
-        } else {
-            tag += 1;
-        }
-
-        self.inner = ((mem_pool_id as usize) << MP_ID_SH)
-            | ((tag as usize) << MP_TAG_SH)
-            | ((index as usize) << MP_SLOT_IDX_SH);
-    }
-
     pub(crate) fn get_index_raw(&self) -> SlotIndex {
         ((self.inner >> MP_SLOT_IDX_SH) & MP_SLOT_IDX_MSK) as SlotIndex
     }
@@ -135,152 +110,667 @@ impl SlotPointer {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlotAllocError {
     PoolFull,
     SlotNotLargeEnough,
+    SlotNotAlignedEnough,
 }
 pub type SlotAllocResult = Result<SlotPointer, SlotAllocError>;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SlotFreeingError {
     SlotOutOfRange,
+    // Only detected by checked pools
+    SlotAlreadyFree,
+    StaleSlotPointer,
 }
 
 pub type SlotFreeingResult = Result<(), SlotFreeingError>;
 
-pub struct SlotPool<const WORDS_PER_POOL: usize> {
-     sto: AsyncArrayCell<usize, WORDS_PER_POOL>,
-     words_per_slot: usize,
-     pool_id: MemPoolId,
+// Storage of a memory pool, whose length is given by `get_words_per_pool()`. Setting
+// NB_CHECKED_SLOTS to the number of slots of the pool enables the checked mode: the live
+// pointer of each slot is recorded so that stale pointers and double frees are rejected
+// instead of corrupting the free list. Only checked pools tag the slot pointers with the
+// allocation number: unchecked ones hand out the same slot pointer for every allocation of a
+// slot, so that a stale pointer cannot be told apart from the live one.
+pub struct SlotPool<const WORDS_PER_POOL: usize, const NB_CHECKED_SLOTS: usize = 0> {
+    sto: SlotPoolStorage<WORDS_PER_POOL>,
+    words_per_slot: usize,
+    slot_stride: usize,
+    slot_align: usize,
+    pool_id: MemPoolId,
+    slot_states: [AtomicRawSlotPointer; NB_CHECKED_SLOTS],
 }
 
-const NEXT_SLOT_NONE: usize = core::usize::MAX;
+const NEXT_SLOT_NONE: usize = usize::MAX;
 
-impl<const WORDS_PER_POOL: usize> SlotPool<WORDS_PER_POOL> {
-    const unsafe fn init_pool_slots(
-        sto: &mut [usize],
-        words_per_slot: usize,
-        pool_id: MemPoolId,
-        idx: usize,
-    ) {
-        match sto {
-            [] => {}
-            [head, rest @ ..] => {
-                if idx % words_per_slot == 0 {
-                    if rest.len() == words_per_slot - 1 {
-                        let empty_slot: &mut EmptySlot = core::mem::transmute(head);
-                        *empty_slot = EmptySlot {
-                            next: AtomicSlotPointer::new(pool_id, None),
-                        };
-                    } else {
-                        let empty_slot: &mut EmptySlot = core::mem::transmute(head);
-                        let slot_index = (idx / words_per_slot + 1) as SlotIndex;
-                        *empty_slot = EmptySlot {
-                            next: AtomicSlotPointer::new(pool_id, Some(slot_index)),
-                        };
-                    }
-                }
-                Self::init_pool_slots(rest, words_per_slot, pool_id, idx + 1);
-            }
+// Largest slot alignment a pool can be declared with
+pub const MAX_SLOT_ALIGN: usize = 32;
+
+// Storage aligned on MAX_SLOT_ALIGN, so that slots whose size is a multiple of their alignment
+// are all aligned
+#[repr(C, align(32))]
+struct SlotPoolStorage<const WORDS_PER_POOL: usize> {
+    inner: AsyncArrayCell<usize, WORDS_PER_POOL>,
+}
+
+const _: () = assert!(core::mem::align_of::<SlotPoolStorage<1>>() == MAX_SLOT_ALIGN);
+
+// Poison all the slots of a storage beyond their free list link, as they are all free, and
+// set their canary
+#[cfg(feature = "slot-poisoning")]
+const fn poison_slots(sto: &mut [usize], slot_stride: usize, words_per_slot: usize) {
+    let mut word_index = 0;
+    while word_index < sto.len() {
+        let slot_word_index = word_index % slot_stride;
+        if slot_word_index >= LINK_WORDS && slot_word_index <= words_per_slot {
+            sto[word_index] = poison::get_free_slot_word(slot_word_index, words_per_slot);
+        }
+        word_index += 1;
+    }
+}
+
+// Smallest slot alignment, for the free list link to be aligned
+pub const MIN_SLOT_ALIGN: usize = {
+    let link_align = core::mem::align_of::<FreeSlot>();
+    let word_align = core::mem::align_of::<usize>();
+    if link_align > word_align {
+        link_align
+    } else {
+        word_align
+    }
+};
+
+// Number of words of the slots able to hold `slot_size` bytes with the smallest alignment
+pub const fn get_words_per_slot(slot_size: usize) -> usize {
+    let words_per_align = MIN_SLOT_ALIGN / core::mem::size_of::<usize>();
+    let words_per_slot = slot_size.div_ceil(core::mem::size_of::<usize>());
+    let words_per_slot = if words_per_slot > LINK_WORDS {
+        words_per_slot
+    } else {
+        LINK_WORDS
+    };
+    words_per_slot.div_ceil(words_per_align) * words_per_align
+}
+
+// Number of words each slot takes in the storage of a pool. The "slot-poisoning" feature
+// reserves a canary word on top of the declared ones, padding the slot to keep it aligned.
+const fn get_slot_stride(words_per_slot: usize, slot_align: usize) -> usize {
+    #[cfg(feature = "slot-poisoning")]
+    {
+        let words_per_align = slot_align / core::mem::size_of::<usize>();
+        (words_per_slot + poison::CANARY_WORDS).div_ceil(words_per_align) * words_per_align
+    }
+    #[cfg(not(feature = "slot-poisoning"))]
+    {
+        let _ = slot_align;
+        words_per_slot
+    }
+}
+
+// Length of the storage of a pool of `nb_slots` slots of `words_per_slot` words with the
+// smallest alignment, see `get_words_per_pool_aligned()`
+pub const fn get_words_per_pool(nb_slots: usize, words_per_slot: usize) -> usize {
+    get_words_per_pool_aligned(nb_slots, words_per_slot, MIN_SLOT_ALIGN)
+}
+
+pub const fn get_words_per_pool_aligned(
+    nb_slots: usize,
+    words_per_slot: usize,
+    slot_align: usize,
+) -> usize {
+    nb_slots * get_slot_stride(words_per_slot, slot_align)
+}
+
+const fn assert_slot_layout(words_per_slot: usize, slot_align: usize) {
+    assert!(words_per_slot > 0, "Slot size cannot be null");
+    assert!(
+        words_per_slot >= LINK_WORDS,
+        "Slot too small to hold the free list link"
+    );
+    assert!(
+        slot_align.is_power_of_two()
+            && slot_align >= MIN_SLOT_ALIGN
+            && slot_align <= MAX_SLOT_ALIGN,
+        "Slot alignment must be a power of two between MIN_SLOT_ALIGN and MAX_SLOT_ALIGN"
+    );
+    assert!(
+        (words_per_slot * core::mem::size_of::<usize>()).is_multiple_of(slot_align),
+        "Slot size must be a multiple of slot alignment"
+    );
+}
+
+const fn assert_nb_slots(nb_slots: usize) {
+    assert!(
+        nb_slots <= (MP_SLOT_IDX_MAX_VAL + 1) as usize,
+        "Too many slots in slot pool"
+    );
+}
+
+// State of a free slot in checked mode. Its index field is MP_SLOT_IDX_NEXT_NONE, so that it
+// never matches the pointer of an allocated slot.
+const FREE_SLOT_STATE: RawSlotPointer = RawSlotPointer::MAX;
+
+// Tag of all the slot pointers of unchecked pools, see `MemoryPool::get_slot_tag()`
+const UNCHECKED_SLOT_TAG: RawSlotPointer = MP_TAG_MIN_VALUE as RawSlotPointer;
+
+// Mark a slot of a checked pool as free, provided that `slot_pointer` is its live pointer.
+// Only one of several contexts freeing the same slot can succeed, the others getting the
+// current state of the slot.
+#[cfg(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr"))]
+fn release_slot_state(
+    slot_state: &AtomicRawSlotPointer,
+    slot_pointer: &SlotPointer,
+) -> Result<(), RawSlotPointer> {
+    slot_state
+        .compare_exchange(
+            slot_pointer.inner,
+            FREE_SLOT_STATE,
+            atomic::Ordering::AcqRel,
+            atomic::Ordering::Acquire,
+        )
+        .map(|_| ())
+}
+
+// Checked and updated in a critical section like the free list, without CAS
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+fn release_slot_state(
+    slot_state: &AtomicRawSlotPointer,
+    slot_pointer: &SlotPointer,
+) -> Result<(), RawSlotPointer> {
+    interrupt::free(|_| match slot_state.load(atomic::Ordering::Acquire) {
+        state if state == slot_pointer.inner => {
+            slot_state.store(FREE_SLOT_STATE, atomic::Ordering::Release);
+            Ok(())
         }
+        state => Err(state),
+    })
+}
+
+impl<const WORDS_PER_POOL: usize, const NB_CHECKED_SLOTS: usize>
+    SlotPool<WORDS_PER_POOL, NB_CHECKED_SLOTS>
+{
+    const fn create_free_list(&self) -> FreeList {
+        FreeList::new(WORDS_PER_POOL / self.slot_stride)
     }
 
-    const fn create_head(&self) -> AtomicSlotPointer {
-        AtomicSlotPointer::new(self.pool_id, Some(0))
+    // Slot pool whose slots have the smallest alignment, a word unless the slot pointers are
+    // wider than words
+    pub const fn new(
+        words_per_slot: usize,
+        pool_id: MemPoolId,
+    ) -> SlotPool<WORDS_PER_POOL, NB_CHECKED_SLOTS> {
+        Self::new_aligned(words_per_slot, pool_id, MIN_SLOT_ALIGN)
     }
 
-    pub const fn new(words_per_slot: usize, pool_id: MemPoolId) -> SlotPool<WORDS_PER_POOL> {
-        assert!(words_per_slot > 0, "Slot size cannot be null");
+    pub const fn new_aligned(
+        words_per_slot: usize,
+        pool_id: MemPoolId,
+        slot_align: usize,
+    ) -> SlotPool<WORDS_PER_POOL, NB_CHECKED_SLOTS> {
+        assert_slot_layout(words_per_slot, slot_align);
+        let slot_stride = get_slot_stride(words_per_slot, slot_align);
         assert!(WORDS_PER_POOL > 0, "Slot pool length cannot be null");
         assert!(
-            WORDS_PER_POOL % words_per_slot == 0,
-            "Slot pool length must be a multiple of slot size"
+            WORDS_PER_POOL.is_multiple_of(slot_stride),
+            "Slot pool length must be a multiple of slot size, see get_words_per_pool()"
         );
+        assert_nb_slots(WORDS_PER_POOL / slot_stride);
         assert!(
-            (WORDS_PER_POOL / words_per_slot) <= (MP_SLOT_IDX_MAX_VAL + 1) as usize,
-            "Too many slots in slot pool"
+            NB_CHECKED_SLOTS == 0 || NB_CHECKED_SLOTS == WORDS_PER_POOL / slot_stride,
+            "Number of checked slots must be the number of slots of the pool"
         );
-        unsafe {
-            let mut sto: [usize; WORDS_PER_POOL] = [0; WORDS_PER_POOL];
-            Self::init_pool_slots(&mut sto, words_per_slot, pool_id, 0);
-            SlotPool {
-                sto: AsyncArrayCell::new(sto),
-                words_per_slot,
-                pool_id,
-            }
+        #[allow(unused_mut)]
+        let mut sto: [usize; WORDS_PER_POOL] = [0; WORDS_PER_POOL];
+        #[cfg(feature = "slot-poisoning")]
+        poison_slots(&mut sto, slot_stride, words_per_slot);
+        SlotPool {
+            sto: SlotPoolStorage {
+                inner: AsyncArrayCell::new(sto),
+            },
+            words_per_slot,
+            slot_stride,
+            slot_align,
+            pool_id,
+            slot_states: [const { AtomicRawSlotPointer::new(FREE_SLOT_STATE) }; NB_CHECKED_SLOTS],
         }
     }
 
-    pub const fn get_slot_pool_ref(&self) -> AsyncArrayCellRef<usize> {
-        self.sto.borrow_mut()
+    pub const fn get_slot_pool_ref(&self) -> AsyncArrayCellRef<'_, usize> {
+        self.sto.inner.borrow_mut()
     }
 }
 
-struct EmptySlot {
-    next: AtomicSlotPointer,
+// Snapshot of the usage of a memory pool
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryPoolStats {
+    pub nb_slots: usize,
+    pub nb_free_slots: usize,
+    // Low-water mark of the number of free slots
+    pub min_nb_free_slots: usize,
+    pub nb_allocations: usize,
+    pub nb_frees: usize,
+    // Allocations that failed because the pool was full
+    pub nb_allocation_failures: usize,
+}
+
+impl MemoryPoolStats {
+    // Accumulate the stats of several pools. The resulting low-water mark is the sum of the
+    // ones of the pools, which may not have been reached at the same time.
+    pub fn merge(&self, other: &MemoryPoolStats) -> MemoryPoolStats {
+        MemoryPoolStats {
+            nb_slots: self.nb_slots + other.nb_slots,
+            nb_free_slots: self.nb_free_slots + other.nb_free_slots,
+            min_nb_free_slots: self.min_nb_free_slots + other.min_nb_free_slots,
+            nb_allocations: self.nb_allocations + other.nb_allocations,
+            nb_frees: self.nb_frees + other.nb_frees,
+            nb_allocation_failures: self.nb_allocation_failures + other.nb_allocation_failures,
+        }
+    }
 }
 
-pub struct MemoryPool<'a> {
+// Counters maintained alongside the free list. The number of free slots is incremented
+// before a slot is pushed back and decremented after it has been popped, so that it never
+// underflows. It may transiently exceed the length of the free list. Their atomics are the
+// ones of the free list, so that the model tests interleave them too.
+#[cfg(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr"))]
+struct MemoryPoolCounters {
+    nb_free_slots: utils_atomic::AtomicUsize,
+    min_nb_free_slots: utils_atomic::AtomicUsize,
+    nb_allocations: utils_atomic::AtomicUsize,
+    nb_frees: utils_atomic::AtomicUsize,
+    nb_allocation_failures: utils_atomic::AtomicUsize,
+}
+
+#[cfg(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr"))]
+impl MemoryPoolCounters {
+    const fn new(nb_slots: usize) -> MemoryPoolCounters {
+        MemoryPoolCounters {
+            nb_free_slots: utils_atomic::AtomicUsize::new(nb_slots),
+            min_nb_free_slots: utils_atomic::AtomicUsize::new(nb_slots),
+            nb_allocations: utils_atomic::AtomicUsize::new(0),
+            nb_frees: utils_atomic::AtomicUsize::new(0),
+            nb_allocation_failures: utils_atomic::AtomicUsize::new(0),
+        }
+    }
+
+    // Return the number of allocations, this one included
+    fn on_allocation(&self) -> usize {
+        let nb_free_slots = self.nb_free_slots.fetch_sub(1, atomic::Ordering::Relaxed) - 1;
+        self.min_nb_free_slots
+            .fetch_min(nb_free_slots, atomic::Ordering::Relaxed);
+        self.nb_allocations.fetch_add(1, atomic::Ordering::Relaxed) + 1
+    }
+
+    fn on_allocation_failure(&self) {
+        self.nb_allocation_failures
+            .fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    fn on_free(&self) {
+        self.nb_free_slots.fetch_add(1, atomic::Ordering::Relaxed);
+        self.nb_frees.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    fn get_stats(&self, nb_slots: usize) -> MemoryPoolStats {
+        MemoryPoolStats {
+            nb_slots,
+            nb_free_slots: self.nb_free_slots.load(atomic::Ordering::Relaxed),
+            min_nb_free_slots: self.min_nb_free_slots.load(atomic::Ordering::Relaxed),
+            nb_allocations: self.nb_allocations.load(atomic::Ordering::Relaxed),
+            nb_frees: self.nb_frees.load(atomic::Ordering::Relaxed),
+            nb_allocation_failures: self.nb_allocation_failures.load(atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+// Same counters, updated within critical sections like the free list when it is protected by
+// them, the target possibly lacking the atomic read-modify-write operations
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+struct MemoryPoolCounters {
+    inner: Mutex<Cell<MemoryPoolStats>>,
+}
+
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+impl MemoryPoolCounters {
+    const fn new(nb_slots: usize) -> MemoryPoolCounters {
+        MemoryPoolCounters {
+            inner: Mutex::new(Cell::new(MemoryPoolStats {
+                nb_slots,
+                nb_free_slots: nb_slots,
+                min_nb_free_slots: nb_slots,
+                nb_allocations: 0,
+                nb_frees: 0,
+                nb_allocation_failures: 0,
+            })),
+        }
+    }
+
+    fn update<R, F: FnOnce(&mut MemoryPoolStats) -> R>(&self, f: F) -> R {
+        interrupt::free(|cs| {
+            let counters = self.inner.borrow(cs);
+            let mut stats = counters.get();
+            let ret = f(&mut stats);
+            counters.set(stats);
+            ret
+        })
+    }
+
+    // Return the number of allocations, this one included
+    fn on_allocation(&self) -> usize {
+        self.update(|stats| {
+            stats.nb_free_slots -= 1;
+            stats.min_nb_free_slots = stats.min_nb_free_slots.min(stats.nb_free_slots);
+            stats.nb_allocations += 1;
+            stats.nb_allocations
+        })
+    }
+
+    fn on_allocation_failure(&self) {
+        self.update(|stats| stats.nb_allocation_failures += 1)
+    }
+
+    fn on_free(&self) {
+        self.update(|stats| {
+            stats.nb_free_slots += 1;
+            stats.nb_frees += 1;
+        })
+    }
+
+    fn get_stats(&self, nb_slots: usize) -> MemoryPoolStats {
+        MemoryPoolStats {
+            nb_slots,
+            ..interrupt::free(|cs| self.inner.borrow(cs).get())
+        }
+    }
+}
+
+pub struct MemoryPool<'a, Tracer: MemoryPoolTracer = NoTracer> {
     id: MemPoolId,
     sto: AsyncArrayCellRef<'a, usize>,
+    // Empty unless the pool is checked
+    slot_states: &'a [AtomicRawSlotPointer],
     words_per_slot: usize,
-    head: AtomicSlotPointer,
+    // Words between the starts of two slots, see `get_slot_stride()`
+    slot_stride: usize,
+    slot_align: usize,
+    free_list: FreeList,
+    counters: MemoryPoolCounters,
+    tracer: Tracer,
+    // Contexts blocked in `allocate_blocking()`, woken up on each free
+    wait_list: WaitList,
+    #[cfg(feature = "slot-poisoning")]
+    fault_handler: FaultHandler,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum SlotAccessError {
     SlotOutOfRange,
     SlotNone,
+    // Only detected by checked pools
+    SlotFree,
+    StaleSlotPointer,
 }
 
 impl<'a> MemoryPool<'a> {
+    pub const fn from<const WORDS_PER_POOL: usize, const NB_CHECKED_SLOTS: usize>(
+        slot_pool: &SlotPool<WORDS_PER_POOL, NB_CHECKED_SLOTS>,
+    ) -> MemoryPool<'_> {
+        MemoryPool::from_with_tracer(slot_pool, NoTracer)
+    }
+
+    // Pool whose slots have the smallest alignment, see `from_region_with_tracer()`
+    pub fn from_region(
+        region: &'a mut [MaybeUninit<u8>],
+        words_per_slot: usize,
+        pool_id: MemPoolId,
+    ) -> MemoryPool<'a> {
+        Self::from_region_aligned(region, words_per_slot, pool_id, MIN_SLOT_ALIGN)
+    }
 
+    pub fn from_region_aligned(
+        region: &'a mut [MaybeUninit<u8>],
+        words_per_slot: usize,
+        pool_id: MemPoolId,
+        slot_align: usize,
+    ) -> MemoryPool<'a> {
+        MemoryPool::from_region_with_tracer(region, words_per_slot, pool_id, slot_align, NoTracer)
+    }
+}
+
+impl<'a, Tracer: MemoryPoolTracer> MemoryPool<'a, Tracer> {
     pub const fn get_mem_pool_id(&self) -> MemPoolId {
         self.id
     }
 
-    pub const fn from<const WORDS_PER_POOL: usize>(
-        slot_pool: &SlotPool<WORDS_PER_POOL>,
-    ) -> MemoryPool {
+    pub const fn from_with_tracer<const WORDS_PER_POOL: usize, const NB_CHECKED_SLOTS: usize>(
+        slot_pool: &SlotPool<WORDS_PER_POOL, NB_CHECKED_SLOTS>,
+        tracer: Tracer,
+    ) -> MemoryPool<'_, Tracer> {
         MemoryPool {
             id: slot_pool.pool_id,
             sto: slot_pool.get_slot_pool_ref(),
+            slot_states: &slot_pool.slot_states,
             words_per_slot: slot_pool.words_per_slot,
-            head: slot_pool.create_head(),
+            slot_stride: slot_pool.slot_stride,
+            slot_align: slot_pool.slot_align,
+            free_list: slot_pool.create_free_list(),
+            counters: MemoryPoolCounters::new(WORDS_PER_POOL / slot_pool.slot_stride),
+            tracer,
+            wait_list: WaitList::new(),
+            #[cfg(feature = "slot-poisoning")]
+            fault_handler: poison::default_fault_handler,
         }
     }
+
+    // Build a pool at runtime over a memory region, such as a RAM section reserved by the
+    // linker script or a buffer allocated at startup, instead of a `SlotPool` static. As many
+    // slots as the region can hold once aligned are linked in the free list.
+    pub fn from_region_with_tracer(
+        region: &'a mut [MaybeUninit<u8>],
+        words_per_slot: usize,
+        pool_id: MemPoolId,
+        slot_align: usize,
+        tracer: Tracer,
+    ) -> MemoryPool<'a, Tracer> {
+        assert_slot_layout(words_per_slot, slot_align);
+        let slot_stride = get_slot_stride(words_per_slot, slot_align);
+        let slot_size = slot_stride * core::mem::size_of::<usize>();
+        let offset = region.as_ptr().align_offset(slot_align).min(region.len());
+        let nb_slots = (region.len() - offset) / slot_size;
+        assert!(nb_slots > 0, "Memory region too small to hold a slot");
+        assert_nb_slots(nb_slots);
+
+        let sto = unsafe {
+            core::slice::from_raw_parts_mut(
+                region.as_mut_ptr().add(offset) as *mut MaybeUninit<usize>,
+                nb_slots * slot_stride,
+            )
+        };
+        sto.iter_mut().for_each(|word| {
+            word.write(0);
+        });
+        #[cfg(feature = "slot-poisoning")]
+        poison_slots(
+            unsafe { &mut *(sto as *mut [MaybeUninit<usize>] as *mut [usize]) },
+            slot_stride,
+            words_per_slot,
+        );
+        MemoryPool {
+            id: pool_id,
+            sto: AsyncArrayCellRef::from_mut_slice(sto),
+            slot_states: &[],
+            words_per_slot,
+            slot_stride,
+            slot_align,
+            free_list: FreeList::new(nb_slots),
+            counters: MemoryPoolCounters::new(nb_slots),
+            tracer,
+            wait_list: WaitList::new(),
+            #[cfg(feature = "slot-poisoning")]
+            fault_handler: poison::default_fault_handler,
+        }
+    }
+
+    #[cfg(feature = "slot-poisoning")]
+    pub const fn with_fault_handler(mut self, fault_handler: FaultHandler) -> Self {
+        self.fault_handler = fault_handler;
+        self
+    }
+
+    pub const fn get_tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    pub const fn get_wait_list(&self) -> &WaitList {
+        &self.wait_list
+    }
     pub const fn get_slot_size(&self) -> usize {
         self.words_per_slot * core::mem::size_of::<usize>()
     }
 
+    pub const fn get_slot_align(&self) -> usize {
+        self.slot_align
+    }
+
+    #[cfg(feature = "slot-poisoning")]
+    fn report_fault(&self, kind: MemoryPoolFaultKind, slot_pointer: &SlotPointer) {
+        (self.fault_handler)(&MemoryPoolFault {
+            kind,
+            pool_id: self.id,
+            slot_index: slot_pointer.get_index_raw(),
+        });
+    }
+
+    // Words of an owned slot following its link: the poisoned area, then the canary
+    #[cfg(feature = "slot-poisoning")]
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_free_slot_words(&self, slot_pointer: &SlotPointer) -> &mut [usize] {
+        let slot = self.get_slot_raw(slot_pointer).unwrap() as *mut usize;
+        core::slice::from_raw_parts_mut(
+            slot.add(LINK_WORDS),
+            self.words_per_slot + poison::CANARY_WORDS - LINK_WORDS,
+        )
+    }
+
+    #[cfg(feature = "slot-poisoning")]
+    unsafe fn check_canary_and_poison(&self, slot_pointer: &SlotPointer) {
+        let (canary, poisoned) = self
+            .get_free_slot_words(slot_pointer)
+            .split_last_mut()
+            .unwrap();
+        if *canary != poison::CANARY_PATTERN {
+            self.report_fault(MemoryPoolFaultKind::CanaryCorrupted, slot_pointer);
+            *canary = poison::CANARY_PATTERN;
+        }
+        poisoned.fill(poison::POISON_PATTERN);
+    }
+
+    #[cfg(feature = "slot-poisoning")]
+    unsafe fn check_poison(&self, slot_pointer: &SlotPointer) {
+        let (canary, poisoned) = self
+            .get_free_slot_words(slot_pointer)
+            .split_last_mut()
+            .unwrap();
+        let intact = poisoned.iter().all(|word| *word == poison::POISON_PATTERN)
+            && *canary == poison::CANARY_PATTERN;
+        *canary = poison::CANARY_PATTERN;
+        if !intact {
+            self.report_fault(MemoryPoolFaultKind::PoisonCorrupted, slot_pointer);
+        }
+    }
+
     fn get_nb_slot(&self) -> usize {
-        // let sto = &*(*self.sto.get()) as &[usize];
-        self.sto.len() / self.words_per_slot
+        self.sto.len() / self.slot_stride
     }
 
-    pub fn get_slot_raw_mut(
-        &self,
-        slot_pointer: &SlotPointer,
-    ) -> Result<*mut u8, SlotAccessError> {
+    pub fn get_stats(&self) -> MemoryPoolStats {
+        self.counters.get_stats(self.get_nb_slot())
+    }
+
+    pub const fn is_checked(&self) -> bool {
+        !self.slot_states.is_empty()
+    }
+
+    fn get_slot_index(&self, ptr: *const u8) -> Option<usize> {
+        let slot_size = self.slot_stride * core::mem::size_of::<usize>();
+        let offset = ptr.addr().checked_sub(self.sto.as_ptr().addr())?;
+        let slot_index = offset / slot_size;
+        if !offset.is_multiple_of(slot_size) || slot_index >= self.get_nb_slot() {
+            return None;
+        }
+        Some(slot_index)
+    }
+
+    // Rebuild the slot pointer of an allocated slot from the address of its memory, for the
+    // users only keeping the address. Checked pools return the live pointer of the slot. Others
+    // always allocate with the same tag, see `get_slot_tag()`.
+    pub fn get_slot_pointer(&self, ptr: *const u8) -> Option<SlotPointer> {
+        let slot_index = self.get_slot_index(ptr)?;
+        if let Some(slot_state) = self.slot_states.get(slot_index) {
+            match slot_state.load(atomic::Ordering::Acquire) {
+                FREE_SLOT_STATE => None,
+                live_slot_pointer => Some(SlotPointer::from(live_slot_pointer)),
+            }
+        } else {
+            Some(SlotPointer::new(
+                self.id,
+                slot_index as SlotIndex,
+                UNCHECKED_SLOT_TAG,
+            ))
+        }
+    }
+
+    // Checked pools tag the slot pointers with the allocation number, so as to tell the pointers
+    // to the previous allocations of a slot apart. Others do not record the tag of the live
+    // pointers, so that they tag them all alike.
+    fn get_slot_tag(&self, nb_allocations: usize) -> RawSlotPointer {
+        if self.slot_states.is_empty() {
+            UNCHECKED_SLOT_TAG
+        } else {
+            nb_allocations as RawSlotPointer
+        }
+    }
+
+    fn get_slot_state(&self, slot_pointer: &SlotPointer) -> Option<&AtomicRawSlotPointer> {
+        self.slot_states.get(slot_pointer.get_index_raw() as usize)
+    }
+
+    // Get the slot of an allocated slot pointer. Checked pools also validate the tag of the
+    // pointer against the one the slot has been allocated with.
+    pub fn get_slot_raw_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, SlotAccessError> {
+        let raw_ptr = self.get_slot_raw(slot_pointer)?;
+        if let Some(slot_state) = self.get_slot_state(slot_pointer) {
+            let slot_state = slot_state.load(atomic::Ordering::Acquire);
+            if slot_state == FREE_SLOT_STATE {
+                return Err(SlotAccessError::SlotFree);
+            } else if slot_state != slot_pointer.inner {
+                return Err(SlotAccessError::StaleSlotPointer);
+            }
+        }
+        Ok(raw_ptr)
+    }
+
+    fn get_slot_raw(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, SlotAccessError> {
         let slot_index = slot_pointer.get_index_raw();
-        if slot_index >= MP_SLOT_IDX_MIN_VAL && slot_index < self.get_nb_slot() as SlotIndex {
+        if slot_index < self.get_nb_slot() as SlotIndex {
             unsafe {
-                let sto = self.sto.deref_mut();
-                let raw_ptr = sto
+                let raw_ptr = self
+                    .sto
                     .as_ptr()
-                    .add((slot_index as usize) * self.words_per_slot);
-                Ok(core::mem::transmute(raw_ptr))
+                    .add((slot_index as usize) * self.slot_stride);
+                Ok(raw_ptr as *mut u8)
             }
+        } else if slot_index == MP_SLOT_IDX_NEXT_NONE {
+            Err(SlotAccessError::SlotNone)
         } else {
-            if slot_index == MP_SLOT_IDX_NEXT_NONE {
-                return Err(SlotAccessError::SlotNone);
-            } else {
-                return Err(SlotAccessError::SlotOutOfRange);
-            }
+            Err(SlotAccessError::SlotOutOfRange)
         }
     }
 
     // Get a slot from the memory pool using a SlotPointer object
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn get_slot_transmute<T>(
         &self,
         slot_pointer: &SlotPointer,
@@ -293,82 +783,99 @@ impl<'a> MemoryPool<'a> {
         }
     }
 
-    fn get_empty_slot(
-        &self,
-        slot_pointer: &SlotPointer,
-    ) -> Result<*const EmptySlot, SlotAccessError> {
-        self.get_slot_raw_mut(slot_pointer)
-            .map(|x: *mut u8| x as *const EmptySlot)
-    }
-
-    fn get_empty_slot_mut(
-        &self,
-        slot_pointer: &SlotPointer,
-    ) -> Result<*mut EmptySlot, SlotAccessError> {
-        self.get_slot_raw_mut(slot_pointer)
-            .map(|x: *mut u8| x as *mut EmptySlot)
-    }
-
     pub unsafe fn free(&self, slot_pointer: SlotPointer) -> SlotFreeingResult {
-        println!(
-            "pool {}: Freeing  slot {:?}",
-            self.id,
-            slot_pointer.get_index()
-        );
-        let new_head_slot = self
-            .get_empty_slot_mut(&slot_pointer)
+        let slot = self
+            .get_slot_raw(&slot_pointer)
             .map_err(|_| SlotFreeingError::SlotOutOfRange)?;
-        loop {
-            let head = self.head.load(atomic::Ordering::Relaxed);
-            println!("old_head {:?}", head.get_index());
-            *new_head_slot = EmptySlot {
-                next: AtomicSlotPointer::from(head),
-            };
-            if let Err(_) = self.head.compare_exchange_weak(
-                head,
-                slot_pointer,
-                atomic::Ordering::Release,
-                atomic::Ordering::Relaxed,
-            ) {
-                continue;
-            } else {
-                return Ok(());
+        if let Some(slot_state) = self.get_slot_state(&slot_pointer) {
+            if let Err(slot_state) = release_slot_state(slot_state, &slot_pointer) {
+                return Err(if slot_state == FREE_SLOT_STATE {
+                    SlotFreeingError::SlotAlreadyFree
+                } else {
+                    SlotFreeingError::StaleSlotPointer
+                });
             }
         }
+        #[cfg(feature = "slot-poisoning")]
+        self.check_canary_and_poison(&slot_pointer);
+        self.counters.on_free();
+        self.free_list
+            .push(NonNull::new_unchecked(slot as *mut FreeSlot), || {
+                self.tracer.on_cas_retry(self.id)
+            });
+        self.tracer.on_free(self.id, slot_pointer.get_index_raw());
+        self.wait_list.notify();
+        Ok(())
     }
 
     pub fn allocate(&self, layout: core::alloc::Layout) -> SlotAllocResult {
         if layout.size() > (self.get_slot_size()) {
             return Err(SlotAllocError::SlotNotLargeEnough);
         }
-        loop {
-            let mut head = self.head.load(atomic::Ordering::Acquire);
-
-            println!("pool {}: Allocating slot {:?}", self.id, head.get_index());
-            if let Ok(head_slot) = self.get_empty_slot(&head) {
-                unsafe {
-                    let head_next = &(*head_slot).next;
-                    let new_head = head_next.load(atomic::Ordering::Relaxed);
-                    println!("head_next {:?}", new_head.get_index());
-                    if let Err(_) = self.head.compare_exchange_weak(
-                        head,
-                        new_head,
-                        atomic::Ordering::Release,
-                        atomic::Ordering::Relaxed,
-                    ) {
-                        continue;
-                    }
-                }
-                head.increment_tag();
-                return Ok(head);
-            } else {
-                return Err(SlotAllocError::PoolFull);
+        if layout.align() > self.slot_align {
+            return Err(SlotAllocError::SlotNotAlignedEnough);
+        }
+        let slot_index = self
+            .free_list
+            .pop(|| self.tracer.on_cas_retry(self.id))
+            .and_then(|slot| self.get_slot_index(slot.as_ptr() as *const u8))
+            .or_else(|| self.free_list.take_fresh());
+        if let Some(slot_index) = slot_index {
+            let nb_allocations = self.counters.on_allocation();
+            let slot_pointer = SlotPointer::new(
+                self.id,
+                slot_index as SlotIndex,
+                self.get_slot_tag(nb_allocations),
+            );
+            #[cfg(feature = "slot-poisoning")]
+            unsafe {
+                self.check_poison(&slot_pointer);
+            }
+            self.tracer
+                .on_allocation(self.id, slot_pointer.get_index_raw());
+            if let Some(slot_state) = self.get_slot_state(&slot_pointer) {
+                slot_state.store(slot_pointer.inner, atomic::Ordering::Release);
             }
+            Ok(slot_pointer)
+        } else {
+            self.counters.on_allocation_failure();
+            self.tracer
+                .on_allocation_failure(self.id, SlotAllocError::PoolFull);
+            Err(SlotAllocError::PoolFull)
         }
     }
+
+    // Wait for a slot to be freed by another context while the pool is full. Every failed
+    // attempt is counted and traced as an allocation failure.
+    pub fn allocate_blocking(
+        &self,
+        layout: core::alloc::Layout,
+        timeout: Duration,
+    ) -> SlotAllocResult {
+        self.wait_list
+            .wait(timeout, || match self.allocate(layout) {
+                Err(SlotAllocError::PoolFull) => None,
+                res => Some(res),
+            })
+            .unwrap_or(Err(SlotAllocError::PoolFull))
+    }
+}
+
+impl<'a, Tracer: MemoryPoolTracer> BlockingAllocator<SlotPointer, SlotAllocError>
+    for MemoryPool<'a, Tracer>
+{
+    fn allocate_blocking(
+        &self,
+        layout: core::alloc::Layout,
+        timeout: Duration,
+    ) -> Result<SlotPointer, SlotAllocError> {
+        self.allocate_blocking(layout, timeout)
+    }
 }
 
-impl<'a> Allocator<SlotPointer, SlotFreeingError, SlotAllocError> for MemoryPool<'a> {
+impl<'a, Tracer: MemoryPoolTracer> Allocator<SlotPointer, SlotFreeingError, SlotAllocError>
+    for MemoryPool<'a, Tracer>
+{
     unsafe fn free(&self, slot_pointer: SlotPointer) -> Result<(), SlotFreeingError> {
         self.free(slot_pointer)
     }
@@ -378,7 +885,7 @@ impl<'a> Allocator<SlotPointer, SlotFreeingError, SlotAllocError> for MemoryPool
     }
 }
 
-impl<'a> MemoryAccessor<SlotPointer> for MemoryPool<'a> {
+impl<'a, Tracer: MemoryPoolTracer> MemoryAccessor<SlotPointer> for MemoryPool<'a, Tracer> {
     fn get_slot_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, ()> {
         self.get_slot_raw_mut(slot_pointer).map_err(|_| ())
     }
@@ -398,7 +905,8 @@ pub mod tests {
         const POOL0_ID: MemPoolId = 0;
         const POOL0_WORDS_PER_SLOT: usize = 1;
         const POOL0_SLOTS_PER_POOL: usize = 2;
-        const POOL0_WORDS_PER_POOL: usize = POOL0_SLOTS_PER_POOL * POOL0_WORDS_PER_SLOT;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
             SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
         static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
@@ -417,29 +925,25 @@ pub mod tests {
                 let res0 = MEMORY_POOL_0.allocate(core::alloc::Layout::new::<Struct0>());
                 let res0 = res0.unwrap();
                 let struct0_0 = MEMORY_POOL_0.get_slot_transmute(&res0).unwrap();
-                struct0_0.write(Struct0 {
-                    a: core::usize::MAX,
-                });
+                struct0_0.write(Struct0 { a: usize::MAX });
 
                 let res1 = MEMORY_POOL_0.allocate(core::alloc::Layout::new::<Struct0>());
                 let res1 = res1.unwrap();
 
                 let struct0_1 = MEMORY_POOL_0.get_slot_transmute(&res1).unwrap();
-                struct0_1.write(Struct0 {
-                    a: core::usize::MIN,
-                });
+                struct0_1.write(Struct0 { a: usize::MIN });
 
                 let res2 = MEMORY_POOL_0.allocate(core::alloc::Layout::new::<Struct0>());
                 assert_eq!(res2, Err(SlotAllocError::PoolFull));
 
-                assert_eq!(struct0_0.assume_init_mut().a, core::usize::MAX);
-                assert_eq!(struct0_1.assume_init_mut().a, core::usize::MIN);
+                assert_eq!(struct0_0.assume_init_mut().a, usize::MAX);
+                assert_eq!(struct0_1.assume_init_mut().a, usize::MIN);
 
                 MEMORY_POOL_0.free(res1).unwrap();
-                assert_eq!(struct0_0.assume_init_mut().a, core::usize::MAX);
+                assert_eq!(struct0_0.assume_init_mut().a, usize::MAX);
 
                 let res3 = SlotPointer {
-                    inner: (res1.get_index_raw() + 1) as usize,
+                    inner: (res1.get_index_raw() + 1) as RawSlotPointer,
                 };
                 let res3 = MEMORY_POOL_0.free(res3);
                 assert_eq!(res3, Err(SlotFreeingError::SlotOutOfRange));
@@ -450,13 +954,12 @@ pub mod tests {
                 let res4 = MEMORY_POOL_0.allocate(core::alloc::Layout::new::<Struct0>());
                 let res4 = res4.unwrap();
 
-                
                 let struct0_4 = MEMORY_POOL_0.get_slot_transmute(&res4).unwrap();
                 struct0_4.write(Struct0 {
                     a: 0xAAAAAAAAAAAAAAAA,
                 });
 
-                assert_eq!(struct0_0.assume_init_mut().a, core::usize::MAX);
+                assert_eq!(struct0_0.assume_init_mut().a, usize::MAX);
 
                 let res0 = MEMORY_POOL_0.free(res0);
                 assert_eq!(res0, Ok(()));
@@ -468,6 +971,524 @@ pub mod tests {
         }
     }
 
+    mod mem_pool_stats_test {
+        use super::*;
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 2;
+        const POOL0_SLOTS_PER_POOL: usize = 3;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+
+        #[test]
+        fn mem_pool_stats_test() {
+            let layout = core::alloc::Layout::new::<usize>();
+            assert_eq!(
+                MEMORY_POOL_0.get_stats(),
+                MemoryPoolStats {
+                    nb_slots: 3,
+                    nb_free_slots: 3,
+                    min_nb_free_slots: 3,
+                    nb_allocations: 0,
+                    nb_frees: 0,
+                    nb_allocation_failures: 0,
+                }
+            );
+
+            unsafe {
+                let slot_pointers: Vec<_> = (0..POOL0_SLOTS_PER_POOL)
+                    .map(|_| MEMORY_POOL_0.allocate(layout).unwrap())
+                    .collect();
+                assert_eq!(
+                    MEMORY_POOL_0.allocate(layout),
+                    Err(SlotAllocError::PoolFull)
+                );
+                // Requests the pool can never serve are not counted as failures
+                assert_eq!(
+                    MEMORY_POOL_0.allocate(core::alloc::Layout::new::<[usize; 3]>()),
+                    Err(SlotAllocError::SlotNotLargeEnough)
+                );
+                for slot_pointer in slot_pointers {
+                    MEMORY_POOL_0.free(slot_pointer).unwrap();
+                }
+                let slot_pointer = MEMORY_POOL_0.allocate(layout).unwrap();
+                assert_eq!(
+                    MEMORY_POOL_0.get_stats(),
+                    MemoryPoolStats {
+                        nb_slots: 3,
+                        nb_free_slots: 2,
+                        min_nb_free_slots: 0,
+                        nb_allocations: 4,
+                        nb_frees: 3,
+                        nb_allocation_failures: 1,
+                    }
+                );
+                MEMORY_POOL_0.free(slot_pointer).unwrap();
+            }
+        }
+    }
+
+    mod aligned_mem_pool_test {
+        use super::*;
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_SLOT_ALIGN: usize = MAX_SLOT_ALIGN;
+        const POOL0_WORDS_PER_SLOT: usize = POOL0_SLOT_ALIGN / core::mem::size_of::<usize>();
+        const POOL0_SLOTS_PER_POOL: usize = 3;
+        const POOL0_WORDS_PER_POOL: usize = get_words_per_pool_aligned(
+            POOL0_SLOTS_PER_POOL,
+            POOL0_WORDS_PER_SLOT,
+            POOL0_SLOT_ALIGN,
+        );
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new_aligned(
+                POOL0_WORDS_PER_SLOT,
+                POOL0_ID,
+                POOL0_SLOT_ALIGN,
+            );
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+
+        #[repr(align(32))]
+        struct Aligned32(u8);
+
+        #[test]
+        fn aligned_mem_pool_test() {
+            let layout = core::alloc::Layout::new::<Aligned32>();
+            assert_eq!(MEMORY_POOL_0.get_slot_align(), POOL0_SLOT_ALIGN);
+            // The declared size is kept whatever the features, a canary padding the slot
+            assert_eq!(
+                MEMORY_POOL_0.get_slot_size(),
+                POOL0_WORDS_PER_SLOT * core::mem::size_of::<usize>()
+            );
+            assert_eq!(MEMORY_POOL_0.get_stats().nb_slots, POOL0_SLOTS_PER_POOL);
+            unsafe {
+                let slot_pointers: Vec<_> = (0..POOL0_SLOTS_PER_POOL)
+                    .map(|_| MEMORY_POOL_0.allocate(layout).unwrap())
+                    .collect();
+                for slot_pointer in slot_pointers.iter() {
+                    let slot = MEMORY_POOL_0.get_slot_raw_mut(slot_pointer).unwrap();
+                    assert!((slot as usize).is_multiple_of(POOL0_SLOT_ALIGN));
+                    // Filling the whole slot leaves the canary of the slot intact
+                    slot.write_bytes(0xA5, MEMORY_POOL_0.get_slot_size());
+                }
+                for slot_pointer in slot_pointers {
+                    MEMORY_POOL_0.free(slot_pointer).unwrap();
+                }
+            }
+        }
+
+        #[test]
+        fn unaligned_mem_pool_test() {
+            const POOL0_UNALIGNED_WORDS_PER_POOL: usize =
+                get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+            static STATIC_MEMORY_POOL: SlotPool<POOL0_UNALIGNED_WORDS_PER_POOL> =
+                SlotPool::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+            static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+            assert_eq!(
+                MEMORY_POOL_0.get_slot_align(),
+                core::mem::align_of::<usize>()
+            );
+            assert_eq!(
+                MEMORY_POOL_0.get_slot_size(),
+                POOL0_WORDS_PER_SLOT * core::mem::size_of::<usize>()
+            );
+            assert_eq!(
+                MEMORY_POOL_0.allocate(core::alloc::Layout::new::<Aligned32>()),
+                Err(SlotAllocError::SlotNotAlignedEnough)
+            );
+            assert_eq!(MEMORY_POOL_0.get_stats().nb_allocation_failures, 0);
+        }
+    }
+
+    #[test]
+    fn slot_pointer_layout_test() {
+        assert_eq!(MP_ID_MSK & MP_TAG_MSK, 0);
+        assert_eq!(MP_TAG_MSK & MP_SLOT_IDX_MSK, 0);
+        assert_eq!(
+            MP_ID_MSK | MP_TAG_MSK | MP_SLOT_IDX_MSK,
+            RawSlotPointer::MAX
+        );
+        assert!(get_words_per_slot(1) >= LINK_WORDS);
+        let min_slot_size = get_words_per_slot(1) * core::mem::size_of::<usize>();
+        assert!(min_slot_size.is_multiple_of(MIN_SLOT_ALIGN));
+
+        let slot_pointer = SlotPointer::new(MemPoolId::MAX, MP_SLOT_IDX_MAX_VAL, 2);
+        assert_eq!(slot_pointer.get_mem_pool_id(), MemPoolId::MAX);
+        assert_eq!(slot_pointer.get_index(), Some(MP_SLOT_IDX_MAX_VAL));
+        assert_eq!((slot_pointer.inner & MP_TAG_MSK) >> MP_TAG_SH, 2);
+
+        // Tags wrap around without overflowing on the other fields
+        let tag = (MP_TAG_MSK >> MP_TAG_SH) + 3;
+        let slot_pointer = SlotPointer::new(MemPoolId::MAX - 1, MP_SLOT_IDX_MIN_VAL, tag);
+        assert_eq!(slot_pointer.get_mem_pool_id(), MemPoolId::MAX - 1);
+        assert_eq!(slot_pointer.get_index(), Some(MP_SLOT_IDX_MIN_VAL));
+        assert_eq!((slot_pointer.inner & MP_TAG_MSK) >> MP_TAG_SH, 2);
+    }
+
+    mod region_mem_pool_test {
+        use super::*;
+        use std::sync::OnceLock;
+
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+        const POOL0_WORDS_PER_SLOT: usize = 4;
+        const POOL0_SLOT_ALIGN: usize = 4 * WORD_SIZE;
+        // Large pool, within what the slot pointer layout can index
+        const POOL0_NB_SLOTS: usize = if (MP_SLOT_IDX_MAX_VAL as usize) < 10_000 {
+            MP_SLOT_IDX_MAX_VAL as usize
+        } else {
+            10_000
+        };
+
+        fn leak_region(len: usize) -> &'static mut [MaybeUninit<u8>] {
+            Vec::leak(vec![MaybeUninit::uninit(); len])
+        }
+
+        #[test]
+        fn region_mem_pool_test() {
+            static MEMORY_POOL_0: OnceLock<MemoryPool<'static>> = OnceLock::new();
+            // One spare slot, so that every slot fits whatever the alignment of the buffer
+            let region = leak_region(
+                get_words_per_pool_aligned(
+                    POOL0_NB_SLOTS + 1,
+                    POOL0_WORDS_PER_SLOT,
+                    POOL0_SLOT_ALIGN,
+                ) * WORD_SIZE,
+            );
+            let memory_pool = MEMORY_POOL_0.get_or_init(|| {
+                MemoryPool::from_region_aligned(region, POOL0_WORDS_PER_SLOT, 0, POOL0_SLOT_ALIGN)
+            });
+            let nb_slots = memory_pool.get_stats().nb_slots;
+            assert!(nb_slots == POOL0_NB_SLOTS || nb_slots == POOL0_NB_SLOTS + 1);
+
+            let layout = core::alloc::Layout::from_size_align(1, POOL0_SLOT_ALIGN).unwrap();
+            unsafe {
+                let slot_pointers: Vec<_> = (0..nb_slots)
+                    .map(|_| memory_pool.allocate(layout).unwrap())
+                    .collect();
+                assert_eq!(memory_pool.allocate(layout), Err(SlotAllocError::PoolFull));
+                for (slot_index, slot_pointer) in slot_pointers.iter().enumerate() {
+                    assert_eq!(slot_pointer.get_index(), Some(slot_index as SlotIndex));
+                    let slot = memory_pool.get_slot_raw_mut(slot_pointer).unwrap();
+                    assert!((slot as usize).is_multiple_of(POOL0_SLOT_ALIGN));
+                    slot.write_bytes(slot_index as u8, memory_pool.get_slot_size());
+                }
+                for slot_pointer in slot_pointers {
+                    memory_pool.free(slot_pointer).unwrap();
+                }
+            }
+            assert_eq!(memory_pool.get_stats().nb_free_slots, nb_slots);
+        }
+
+        #[test]
+        #[should_panic(expected = "too small")]
+        fn region_too_small_test() {
+            MemoryPool::from_region(&mut [MaybeUninit::uninit(); WORD_SIZE], 2, 0);
+        }
+    }
+
+    #[cfg(kaori_model)]
+    mod model_mem_pool_test {
+        use super::*;
+        use crate::utils::model;
+        use std::sync::atomic::AtomicBool;
+
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+        const POOL0_WORDS_PER_SLOT: usize = get_words_per_slot(WORD_SIZE);
+        const POOL0_NB_SLOTS: usize = 2;
+
+        #[test]
+        fn model_mem_pool_test() {
+            let layout = core::alloc::Layout::new::<usize>();
+            model::check(|| {
+                // The region is trimmed to the aligned slots, so that the pool has the same slots
+                // in every execution whatever the alignment of the buffer, which Miri randomizes
+                let region_len =
+                    get_words_per_pool(POOL0_NB_SLOTS, POOL0_WORDS_PER_SLOT) * WORD_SIZE;
+                let mut buffer = vec![MaybeUninit::uninit(); region_len + WORD_SIZE];
+                let offset = buffer.as_ptr().align_offset(WORD_SIZE);
+                let region = &mut buffer[offset..offset + region_len];
+                let memory_pool = MemoryPool::from_region(region, POOL0_WORDS_PER_SLOT, 0);
+                let nb_slots = memory_pool.get_stats().nb_slots;
+                assert_eq!(nb_slots, POOL0_NB_SLOTS);
+                let allocated: Vec<_> = (0..nb_slots).map(|_| AtomicBool::new(false)).collect();
+
+                // The slots are handed out to a single context at a time, which keeps the value
+                // it writes in them until it frees them
+                let allocate = |value: usize| {
+                    let slot_pointer = memory_pool.allocate(layout).ok()?;
+                    let slot_index = slot_pointer.get_index().unwrap() as usize;
+                    assert!(
+                        !allocated[slot_index].swap(true, atomic::Ordering::Relaxed),
+                        "Slot {slot_index} handed out twice"
+                    );
+                    unsafe {
+                        (memory_pool.get_slot_raw_mut(&slot_pointer).unwrap() as *mut usize)
+                            .write(value)
+                    };
+                    Some((slot_pointer, value))
+                };
+                let free = |(slot_pointer, value): (SlotPointer, usize)| unsafe {
+                    let slot = memory_pool.get_slot_raw_mut(&slot_pointer).unwrap();
+                    assert_eq!((slot as *const usize).read(), value);
+                    allocated[slot_pointer.get_index().unwrap() as usize]
+                        .store(false, atomic::Ordering::Relaxed);
+                    memory_pool.free(slot_pointer).unwrap();
+                };
+                // Every slot is linked, so that the allocations go through the free list
+                let slots: Vec<_> = (0..nb_slots).map_while(|_| allocate(0)).collect();
+                slots.into_iter().for_each(free);
+
+                let nb_kept = atomic::AtomicUsize::new(0);
+                let allocate_free = || allocate(1).into_iter().for_each(free);
+                let allocate_twice_free = || {
+                    let first = allocate(2);
+                    if allocate(3).is_some() {
+                        nb_kept.fetch_add(1, atomic::Ordering::Relaxed);
+                    }
+                    first.into_iter().for_each(free);
+                };
+                model::threads(&[&allocate_free, &allocate_twice_free]);
+
+                // No slot has been lost, nor miscounted
+                let nb_free_slots = core::iter::from_fn(|| allocate(4)).count();
+                assert_eq!(
+                    nb_free_slots + nb_kept.load(atomic::Ordering::Relaxed),
+                    nb_slots
+                );
+                let stats = memory_pool.get_stats();
+                assert_eq!(stats.nb_free_slots, 0);
+                assert_eq!(stats.min_nb_free_slots, 0);
+                assert_eq!(stats.nb_allocations - stats.nb_frees, nb_slots);
+            });
+        }
+    }
+
+    mod checked_mem_pool_test {
+        use super::*;
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 1;
+        const POOL0_SLOTS_PER_POOL: usize = 2;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL, POOL0_SLOTS_PER_POOL> =
+            SlotPool::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+
+        #[test]
+        fn checked_mem_pool_test() {
+            let layout = core::alloc::Layout::new::<usize>();
+            assert!(MEMORY_POOL_0.is_checked());
+            unsafe {
+                for _ in 0..4 {
+                    let res0 = MEMORY_POOL_0.allocate(layout).unwrap();
+                    assert!(MEMORY_POOL_0.get_slot_raw_mut(&res0).is_ok());
+                    MEMORY_POOL_0.free(res0).unwrap();
+                    assert_eq!(
+                        MEMORY_POOL_0.get_slot_raw_mut(&res0),
+                        Err(SlotAccessError::SlotFree)
+                    );
+                    assert_eq!(
+                        MEMORY_POOL_0.free(res0),
+                        Err(SlotFreeingError::SlotAlreadyFree)
+                    );
+
+                    // Same slot, allocated again with a new tag
+                    let res1 = MEMORY_POOL_0.allocate(layout).unwrap();
+                    assert_eq!(res1.get_index_raw(), res0.get_index_raw());
+                    assert_eq!(
+                        MEMORY_POOL_0.get_slot_raw_mut(&res0),
+                        Err(SlotAccessError::StaleSlotPointer)
+                    );
+                    assert_eq!(
+                        MEMORY_POOL_0.free(res0),
+                        Err(SlotFreeingError::StaleSlotPointer)
+                    );
+                    assert!(MEMORY_POOL_0.get_slot_raw_mut(&res1).is_ok());
+                    MEMORY_POOL_0.free(res1).unwrap();
+                }
+
+                // The free list survived the rejected frees
+                let res0 = MEMORY_POOL_0.allocate(layout).unwrap();
+                let res1 = MEMORY_POOL_0.allocate(layout).unwrap();
+                assert_ne!(res0.get_index_raw(), res1.get_index_raw());
+                assert_eq!(
+                    MEMORY_POOL_0.allocate(layout),
+                    Err(SlotAllocError::PoolFull)
+                );
+                MEMORY_POOL_0.free(res0).unwrap();
+                MEMORY_POOL_0.free(res1).unwrap();
+            }
+        }
+
+        #[test]
+        fn unchecked_mem_pool_test() {
+            static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+                SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+            static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+            assert!(!MEMORY_POOL_0.is_checked());
+            unsafe {
+                let res0 = MEMORY_POOL_0
+                    .allocate(core::alloc::Layout::new::<usize>())
+                    .unwrap();
+                MEMORY_POOL_0.free(res0).unwrap();
+                assert!(MEMORY_POOL_0.get_slot_raw_mut(&res0).is_ok());
+
+                // Allocating the slot again hands out the same slot pointer
+                let res1 = MEMORY_POOL_0
+                    .allocate(core::alloc::Layout::new::<usize>())
+                    .unwrap();
+                assert_eq!(res1, res0);
+                let slot = MEMORY_POOL_0.get_slot_raw_mut(&res1).unwrap();
+                assert_eq!(MEMORY_POOL_0.get_slot_pointer(slot), Some(res0));
+                MEMORY_POOL_0.free(res1).unwrap();
+            }
+        }
+    }
+
+    #[cfg(feature = "slot-poisoning")]
+    mod poisoned_mem_pool_test {
+        use super::*;
+        use std::sync::Mutex;
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 2;
+        const POOL0_SLOTS_PER_POOL: usize = 1;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static MEMORY_POOL_0: MemoryPool =
+            MemoryPool::from(&STATIC_MEMORY_POOL).with_fault_handler(record_fault);
+
+        static FAULTS: Mutex<Vec<MemoryPoolFault>> = Mutex::new(Vec::new());
+
+        fn record_fault(fault: &MemoryPoolFault) {
+            FAULTS.lock().unwrap().push(*fault);
+        }
+
+        fn take_faults() -> Vec<MemoryPoolFault> {
+            core::mem::take(&mut *FAULTS.lock().unwrap())
+        }
+
+        fn fault(kind: MemoryPoolFaultKind) -> MemoryPoolFault {
+            MemoryPoolFault {
+                kind,
+                pool_id: POOL0_ID,
+                slot_index: 0,
+            }
+        }
+
+        #[test]
+        fn poisoned_mem_pool_test() {
+            let layout = core::alloc::Layout::new::<[usize; 2]>();
+            // The canary is reserved on top of the declared words of the slot
+            assert_eq!(
+                MEMORY_POOL_0.get_slot_size(),
+                2 * core::mem::size_of::<usize>()
+            );
+            unsafe {
+                let res0 = MEMORY_POOL_0.allocate(layout).unwrap();
+                let slot = MEMORY_POOL_0.get_slot_raw_mut(&res0).unwrap() as *mut [usize; 3];
+                (*slot)[0] = usize::MAX;
+                (*slot)[1] = usize::MAX;
+                MEMORY_POOL_0.free(res0).unwrap();
+                assert_eq!(take_faults(), []);
+                assert_eq!((*slot)[1], poison::POISON_PATTERN);
+
+                // Writing to the slot through a dangling pointer
+                (*slot)[1] = 0;
+                let res1 = MEMORY_POOL_0.allocate(layout).unwrap();
+                assert_eq!(take_faults(), [fault(MemoryPoolFaultKind::PoisonCorrupted)]);
+
+                // Overflowing the user area
+                (*slot)[2] = 0;
+                MEMORY_POOL_0.free(res1).unwrap();
+                assert_eq!(take_faults(), [fault(MemoryPoolFaultKind::CanaryCorrupted)]);
+
+                // Both the poison and the canary have been restored
+                let res2 = MEMORY_POOL_0.allocate(layout).unwrap();
+                MEMORY_POOL_0.free(res2).unwrap();
+                assert_eq!(take_faults(), []);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "CanaryCorrupted")]
+        fn default_fault_handler_test() {
+            static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+                SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+            static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+            unsafe {
+                let res0 = MEMORY_POOL_0
+                    .allocate(core::alloc::Layout::new::<usize>())
+                    .unwrap();
+                let slot = MEMORY_POOL_0.get_slot_raw_mut(&res0).unwrap() as *mut [usize; 3];
+                (*slot)[2] = 0;
+                let _ = MEMORY_POOL_0.free(res0);
+            }
+        }
+    }
+
+    mod traced_mem_pool_test {
+        use super::*;
+        use crate::memory_allocation::allocator::memory_pool_allocator::trace::{
+            TraceEvent, TraceRecorder,
+        };
+        const POOL0_ID: MemPoolId = 0;
+        const POOL0_WORDS_PER_SLOT: usize = 1;
+        const POOL0_SLOTS_PER_POOL: usize = 2;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOTS_PER_POOL, POOL0_WORDS_PER_SLOT);
+        static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
+            SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
+        static TRACE_RECORDER: TraceRecorder<8> = TraceRecorder::new();
+        static MEMORY_POOL_0: MemoryPool<&TraceRecorder<8>> =
+            MemoryPool::from_with_tracer(&STATIC_MEMORY_POOL, &TRACE_RECORDER);
+
+        #[test]
+        fn traced_mem_pool_test() {
+            let layout = core::alloc::Layout::new::<usize>();
+            unsafe {
+                let res0 = MEMORY_POOL_0.allocate(layout).unwrap();
+                let res1 = MEMORY_POOL_0.allocate(layout).unwrap();
+                assert_eq!(
+                    MEMORY_POOL_0.allocate(layout),
+                    Err(SlotAllocError::PoolFull)
+                );
+                MEMORY_POOL_0.free(res0).unwrap();
+                MEMORY_POOL_0.free(res1).unwrap();
+            }
+
+            let mut events = Vec::new();
+            MEMORY_POOL_0.get_tracer().for_each(|evt| events.push(*evt));
+            assert_eq!(
+                events,
+                [
+                    TraceEvent::Allocation {
+                        pool_id: POOL0_ID,
+                        slot_index: 0
+                    },
+                    TraceEvent::Allocation {
+                        pool_id: POOL0_ID,
+                        slot_index: 1
+                    },
+                    TraceEvent::AllocationFailure {
+                        pool_id: POOL0_ID,
+                        error: SlotAllocError::PoolFull
+                    },
+                    TraceEvent::Free {
+                        pool_id: POOL0_ID,
+                        slot_index: 0
+                    },
+                    TraceEvent::Free {
+                        pool_id: POOL0_ID,
+                        slot_index: 1
+                    },
+                ]
+            );
+        }
+    }
+
     pub struct Tester<
         'a,
         FreeErrorType: core::fmt::Debug,
@@ -498,8 +1519,8 @@ pub mod tests {
             Self {
                 allocator,
                 reference_allocator: Vec::new(),
-                phantom_free_err: PhantomData::default(),
-                phantom_alloc_err: PhantomData::default(),
+                phantom_free_err: PhantomData,
+                phantom_alloc_err: PhantomData,
             }
         }
 
@@ -523,11 +1544,11 @@ pub mod tests {
         pub fn run_integrity_check(&mut self) {
             for reference_pool_allocator in self.reference_allocator.iter() {
                 for (reference_element, pointer) in reference_pool_allocator {
-                    let element_slot = self.allocator.get_slot_mut(&pointer).unwrap();
-                    for i in 0..reference_element.len() {
+                    let element_slot = self.allocator.get_slot_mut(pointer).unwrap();
+                    for (i, reference_byte) in reference_element.iter().enumerate() {
                         unsafe {
-                            let val = *element_slot.offset(i as isize);
-                            assert!(val == reference_element[i]);
+                            let val = *element_slot.add(i);
+                            assert!(val == *reference_byte);
                         }
                     }
                 }
@@ -615,7 +1636,8 @@ pub mod tests {
         const POOL0_ID: MemPoolId = 0;
         const POOL0_WORDS_PER_SLOT: usize = 8;
         const POOL0_SLOT_PER_POOL: usize = 30;
-        const POOL0_WORDS_PER_POOL: usize = POOL0_SLOT_PER_POOL * POOL0_WORDS_PER_SLOT;
+        const POOL0_WORDS_PER_POOL: usize =
+            get_words_per_pool(POOL0_SLOT_PER_POOL, POOL0_WORDS_PER_SLOT);
         static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
             SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
         static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
@@ -630,7 +1652,7 @@ pub mod tests {
 
             let test_params = TestParams {
                 pool_test_params: &pool_test_params,
-                n_iterations: 10000,
+                n_iterations: if cfg!(miri) { 200 } else { 10000 },
             };
 
             let mut tester = Tester::new(&MEMORY_POOL_0);
@@ -0,0 +1,318 @@
+use crate::port::{interrupt, Mutex};
+use core::cell::Cell;
+use core::ptr::NonNull;
+
+// Backends of the free list of the memory pools. Both link the freed slots through a pointer
+// stored at the start of each of them, and only differ in how the list is updated:
+// - `LockFreeList` is a `treiber::Stack`, swapped with CAS or LL/SC
+// - `CriticalSectionFreeList` is updated with interrupts masked, for cores without CAS such as
+//   Cortex-M0/M0+, or to bound the duration of allocations and frees under contention
+//
+// The slots which have never been allocated are not linked, as slot addresses cannot be
+// computed by the const constructors of the pools. They are taken by ascending index once the
+// list is empty.
+//
+// The lock-free backend is used whenever the target has CAS, unless the "critical-section-pool"
+// feature is enabled.
+#[cfg(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr"))]
+pub use lock_free::{FreeSlot, LockFreeList as FreeList};
+
+#[cfg(not(all(not(feature = "critical-section-pool"), target_has_atomic = "ptr")))]
+pub use critical_section::{CriticalSectionFreeList as FreeList, FreeSlot};
+
+// On 32-bit targets other than ARM, which has LL/SC, the lock-free backend needs a native 64-bit
+// CAS. portable-atomic would otherwise emulate it with a lock, which an interrupt preempting
+// its holder would deadlock on.
+#[cfg(all(
+    not(feature = "critical-section-pool"),
+    target_has_atomic = "ptr",
+    target_pointer_width = "32",
+    not(target_arch = "arm"),
+    not(target_has_atomic = "64")
+))]
+compile_error!(
+    "The lock-free memory pools need a 64-bit CAS on this target, enable the \"critical-section-pool\" feature"
+);
+
+#[cfg(target_has_atomic = "ptr")]
+mod lock_free {
+    use crate::utils::atomic;
+    use crate::utils::treiber::{AtomicPtr, Node, Stack};
+    use core::ptr::NonNull;
+
+    #[repr(C)]
+    pub struct FreeSlot {
+        next: AtomicPtr<FreeSlot>,
+    }
+
+    impl Node for FreeSlot {
+        fn next(&self) -> &AtomicPtr<Self> {
+            &self.next
+        }
+
+        fn next_mut(&mut self) -> &mut AtomicPtr<Self> {
+            &mut self.next
+        }
+    }
+
+    pub struct LockFreeList {
+        stack: Stack<FreeSlot>,
+        next_fresh_slot: atomic::AtomicUsize,
+        nb_slots: usize,
+    }
+
+    impl LockFreeList {
+        pub const fn new(nb_slots: usize) -> LockFreeList {
+            LockFreeList {
+                stack: Stack::new(),
+                next_fresh_slot: atomic::AtomicUsize::new(0),
+                nb_slots,
+            }
+        }
+
+        // `on_retry` is called each time another context modified the list in the meantime
+        pub fn pop<R: FnMut()>(&self, on_retry: R) -> Option<NonNull<FreeSlot>> {
+            self.stack.try_pop_with(on_retry)
+        }
+
+        // Index of the next slot never allocated
+        pub fn take_fresh(&self) -> Option<usize> {
+            self.next_fresh_slot
+                .fetch_update(
+                    atomic::Ordering::Relaxed,
+                    atomic::Ordering::Relaxed,
+                    |next| (next < self.nb_slots).then_some(next + 1),
+                )
+                .ok()
+        }
+
+        // The slot must be owned by the caller, and stay valid while it is in the list. Its
+        // user may have overwritten the link with writes of any size, so that it is reset as a
+        // whole with the atomic type of the stack before being linked again.
+        pub unsafe fn push<R: FnMut()>(&self, slot: NonNull<FreeSlot>, on_retry: R) {
+            slot.as_ref().next.reset();
+            self.stack.push_with(slot, on_retry)
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+#[allow(unused_imports)]
+pub use lock_free::LockFreeList;
+
+mod critical_section {
+    use super::*;
+
+    #[repr(C)]
+    pub struct FreeSlot {
+        next: Option<NonNull<FreeSlot>>,
+    }
+
+    pub struct CriticalSectionFreeList {
+        head: Mutex<Cell<Option<NonNull<FreeSlot>>>>,
+        next_fresh_slot: Mutex<Cell<usize>>,
+        nb_slots: usize,
+    }
+
+    // SAFETY: the slots of the list are only accessed within critical sections
+    unsafe impl Sync for CriticalSectionFreeList {}
+    unsafe impl Send for CriticalSectionFreeList {}
+
+    // Same interface as `LockFreeList`, the list never being contended
+    impl CriticalSectionFreeList {
+        pub const fn new(nb_slots: usize) -> CriticalSectionFreeList {
+            CriticalSectionFreeList {
+                head: Mutex::new(Cell::new(None)),
+                next_fresh_slot: Mutex::new(Cell::new(0)),
+                nb_slots,
+            }
+        }
+
+        pub fn pop<R: FnMut()>(&self, _on_retry: R) -> Option<NonNull<FreeSlot>> {
+            interrupt::free(|cs| {
+                let head = self.head.borrow(cs);
+                let slot = head.get()?;
+                head.set(unsafe { slot.as_ptr().read().next });
+                Some(slot)
+            })
+        }
+
+        pub fn take_fresh(&self) -> Option<usize> {
+            interrupt::free(|cs| {
+                let next_fresh_slot = self.next_fresh_slot.borrow(cs);
+                let next = next_fresh_slot.get();
+                (next < self.nb_slots).then(|| {
+                    next_fresh_slot.set(next + 1);
+                    next
+                })
+            })
+        }
+
+        pub unsafe fn push<R: FnMut()>(&self, slot: NonNull<FreeSlot>, _on_retry: R) {
+            interrupt::free(|cs| {
+                let head = self.head.borrow(cs);
+                slot.as_ptr().write(FreeSlot { next: head.get() });
+                head.set(Some(slot));
+            })
+        }
+    }
+}
+
+#[allow(unused_imports)]
+pub use critical_section::CriticalSectionFreeList;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::UnsafeCell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const NB_SLOTS: usize = 8;
+
+    // Slots of a fake pool, a word each
+    struct Slots(Vec<UnsafeCell<usize>>);
+
+    // SAFETY: the slots are only accessed through the free lists
+    unsafe impl Sync for Slots {}
+    unsafe impl Send for Slots {}
+
+    impl Slots {
+        fn new() -> Arc<Slots> {
+            Arc::new(Slots((0..NB_SLOTS).map(|_| UnsafeCell::new(0)).collect()))
+        }
+
+        // Derived from the whole storage, as a pool does
+        fn get<T>(&self, index: usize) -> NonNull<T> {
+            assert!(index < self.0.len());
+            NonNull::new(UnsafeCell::raw_get(unsafe { self.0.as_ptr().add(index) }))
+                .unwrap()
+                .cast()
+        }
+
+        fn index<T>(&self, slot: NonNull<T>) -> usize {
+            (slot.as_ptr().addr() - self.0.as_ptr().addr()) / core::mem::size_of::<usize>()
+        }
+    }
+
+    // Both backends are driven through the same functions
+    macro_rules! free_list_tests {
+        ($mod:ident, $free_list:ty) => {
+            mod $mod {
+                use super::*;
+
+                fn allocate(free_list: &$free_list, slots: &Slots) -> Option<usize> {
+                    free_list
+                        .pop(|| {})
+                        .map(|slot| slots.index(slot))
+                        .or_else(|| free_list.take_fresh())
+                }
+
+                fn free(free_list: &$free_list, slots: &Slots, index: usize) {
+                    unsafe { free_list.push(slots.get(index), || {}) }
+                }
+
+                #[test]
+                fn lifo_test() {
+                    let slots = Slots::new();
+                    let free_list = <$free_list>::new(NB_SLOTS);
+                    let indices: Vec<_> = (0..NB_SLOTS)
+                        .map(|_| allocate(&free_list, &slots).unwrap())
+                        .collect();
+                    assert_eq!(indices, (0..NB_SLOTS).collect::<Vec<_>>());
+                    assert_eq!(allocate(&free_list, &slots), None);
+
+                    free(&free_list, &slots, 2);
+                    free(&free_list, &slots, 5);
+                    assert_eq!(allocate(&free_list, &slots), Some(5));
+                    assert_eq!(allocate(&free_list, &slots), Some(2));
+                    assert_eq!(allocate(&free_list, &slots), None);
+                }
+
+                #[test]
+                fn concurrent_test() {
+                    const NB_THREADS: usize = 4;
+                    const NB_ITERATIONS: usize = if cfg!(miri) { 100 } else { 10_000 };
+                    let slots = Slots::new();
+                    let free_list = Arc::new(<$free_list>::new(NB_SLOTS));
+                    let nb_allocations = Arc::new(AtomicUsize::new(0));
+                    let threads: Vec<_> = (0..NB_THREADS)
+                        .map(|_| {
+                            let slots = slots.clone();
+                            let free_list = free_list.clone();
+                            let nb_allocations = nb_allocations.clone();
+                            std::thread::spawn(move || {
+                                for _ in 0..NB_ITERATIONS {
+                                    if let Some(index) = allocate(&free_list, &slots) {
+                                        nb_allocations.fetch_add(1, Ordering::Relaxed);
+                                        free(&free_list, &slots, index);
+                                    }
+                                }
+                            })
+                        })
+                        .collect();
+                    threads
+                        .into_iter()
+                        .for_each(|thread| thread.join().unwrap());
+                    assert!(nb_allocations.load(Ordering::Relaxed) > 0);
+
+                    // No slot has been lost nor duplicated
+                    let mut indices: Vec<_> =
+                        std::iter::from_fn(|| allocate(&free_list, &slots)).collect();
+                    indices.sort();
+                    assert_eq!(indices, (0..NB_SLOTS).collect::<Vec<_>>());
+                }
+
+                #[test]
+                #[cfg(kaori_model)]
+                fn model_test() {
+                    use crate::utils::model;
+                    use std::sync::atomic::AtomicBool;
+
+                    const NB_MODEL_SLOTS: usize = 2;
+                    let slots = Slots::new();
+                    model::check(|| {
+                        let free_list = <$free_list>::new(NB_MODEL_SLOTS);
+                        let allocated: [AtomicBool; NB_MODEL_SLOTS] = Default::default();
+                        let allocate = || {
+                            let index = allocate(&free_list, &slots)?;
+                            assert!(
+                                !allocated[index].swap(true, Ordering::Relaxed),
+                                "Slot {index} handed out twice"
+                            );
+                            Some(index)
+                        };
+                        let free = |index: usize| {
+                            allocated[index].store(false, Ordering::Relaxed);
+                            free(&free_list, &slots, index);
+                        };
+                        // Both slots are linked, so that the pops go through the list
+                        allocate().into_iter().chain(allocate()).for_each(free);
+
+                        let kept = AtomicUsize::new(usize::MAX);
+                        let allocate_free = || allocate().into_iter().for_each(free);
+                        let allocate_twice_free = || {
+                            let first = allocate();
+                            if let Some(second) = allocate() {
+                                kept.store(second, Ordering::Relaxed);
+                            }
+                            first.into_iter().for_each(free);
+                        };
+                        model::threads(&[&allocate_free, &allocate_twice_free]);
+
+                        // No slot has been lost nor duplicated
+                        let mut indices: Vec<_> = std::iter::from_fn(allocate)
+                            .chain([kept.load(Ordering::Relaxed)])
+                            .filter(|&index| index != usize::MAX)
+                            .collect();
+                        indices.sort();
+                        assert_eq!(indices, (0..NB_MODEL_SLOTS).collect::<Vec<_>>());
+                    });
+                }
+            }
+        };
+    }
+
+    free_list_tests!(lock_free, LockFreeList);
+    free_list_tests!(critical_section, CriticalSectionFreeList);
+}
@@ -1,12 +1,27 @@
-
 mod allocator;
+mod global_alloc;
 mod memory_pool;
+#[cfg(feature = "slot-poisoning")]
+mod poison;
+mod trace;
 
-pub use memory_pool::{SlotPool,types::MemPoolId,  SlotPointer, MemoryPool, SlotAllocError, SlotAccessError, SlotFreeingError};
+#[allow(unused_imports)]
+pub use allocator::{AllocationError, FreeError, MemoryPoolAllocator};
+#[allow(unused_imports)]
+pub use global_alloc::GlobalMemoryPoolAllocator;
+#[allow(unused_imports)]
+pub use memory_pool::{
+    get_words_per_pool, get_words_per_pool_aligned, get_words_per_slot, SlotAllocError,
+    SlotFreeingError, MAX_SLOT_ALIGN, MIN_SLOT_ALIGN,
+};
+#[allow(unused_imports)]
+pub use memory_pool::{types::MemPoolId, MemoryPool, MemoryPoolStats, SlotPointer, SlotPool};
+#[cfg(feature = "slot-poisoning")]
+#[allow(unused_imports)]
+pub use poison::{FaultHandler, MemoryPoolFault, MemoryPoolFaultKind};
+#[allow(unused_imports)]
+pub use trace::{MemoryPoolTracer, NoTracer, TraceEvent, TraceRecorder};
 
-pub trait MemoryAccessor<PointerType>{
-    fn get_slot_mut(
-        &self,
-        slot_pointer: &SlotPointer,
-    ) -> Result<*mut u8, ()>;
+pub trait MemoryAccessor<PointerType> {
+    fn get_slot_mut(&self, slot_pointer: &SlotPointer) -> Result<*mut u8, ()>;
 }
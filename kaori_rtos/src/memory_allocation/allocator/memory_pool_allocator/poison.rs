@@ -0,0 +1,44 @@
+use super::memory_pool::types::{MemPoolId, SlotIndex};
+
+// Pattern filling the free slots, beyond the word linking them in the free list
+pub const POISON_PATTERN: usize = usize::from_ne_bytes([0xA5; core::mem::size_of::<usize>()]);
+// Pattern of the word following the user area of each slot, reserved on top of its declared
+// size
+pub const CANARY_PATTERN: usize = usize::from_ne_bytes([0xC3; core::mem::size_of::<usize>()]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryPoolFaultKind {
+    // A free slot has been written to, typically through a dangling pointer
+    PoisonCorrupted,
+    // The user area of a slot has been overflowed
+    CanaryCorrupted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryPoolFault {
+    pub kind: MemoryPoolFaultKind,
+    pub pool_id: MemPoolId,
+    pub slot_index: SlotIndex,
+}
+
+// Called on every fault detected by a memory pool. The pool goes on with the allocation or
+// the free once the handler returns, the slot being poisoned and its canary restored.
+pub type FaultHandler = fn(&MemoryPoolFault);
+
+pub fn default_fault_handler(fault: &MemoryPoolFault) {
+    panic!(
+        "Memory pool {} slot {}: {:?}",
+        fault.pool_id, fault.slot_index, fault.kind
+    );
+}
+
+pub const CANARY_WORDS: usize = 1;
+
+// Initial value of the words of a free slot following its link, up to its canary
+pub const fn get_free_slot_word(word_index: usize, words_per_slot: usize) -> usize {
+    if word_index == words_per_slot {
+        CANARY_PATTERN
+    } else {
+        POISON_PATTERN
+    }
+}
@@ -0,0 +1,202 @@
+use super::memory_pool::{types::MemPoolId, types::SlotIndex, SlotAllocError};
+use crate::port::{interrupt, Mutex};
+use core::cell::RefCell;
+
+// Noteworthy operations of a memory pool, as reported to its tracer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    Allocation {
+        pool_id: MemPoolId,
+        slot_index: SlotIndex,
+    },
+    Free {
+        pool_id: MemPoolId,
+        slot_index: SlotIndex,
+    },
+    AllocationFailure {
+        pool_id: MemPoolId,
+        error: SlotAllocError,
+    },
+    // Another context modified the free list in the middle of an allocation or a free
+    CasRetry {
+        pool_id: MemPoolId,
+    },
+}
+
+// Hooks called by a memory pool. The tracer is a type parameter of the pool, so that the
+// default no-op implementations are optimized away when tracing is not used.
+pub trait MemoryPoolTracer: Sync {
+    fn on_allocation(&self, _pool_id: MemPoolId, _slot_index: SlotIndex) {}
+    fn on_free(&self, _pool_id: MemPoolId, _slot_index: SlotIndex) {}
+    fn on_allocation_failure(&self, _pool_id: MemPoolId, _error: SlotAllocError) {}
+    fn on_cas_retry(&self, _pool_id: MemPoolId) {}
+}
+
+// Tracer of the memory pools built with `MemoryPool::from()`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoTracer;
+
+impl MemoryPoolTracer for NoTracer {}
+
+// Lets several pools share a statically allocated tracer
+impl<T: MemoryPoolTracer> MemoryPoolTracer for &T {
+    fn on_allocation(&self, pool_id: MemPoolId, slot_index: SlotIndex) {
+        (*self).on_allocation(pool_id, slot_index)
+    }
+
+    fn on_free(&self, pool_id: MemPoolId, slot_index: SlotIndex) {
+        (*self).on_free(pool_id, slot_index)
+    }
+
+    fn on_allocation_failure(&self, pool_id: MemPoolId, error: SlotAllocError) {
+        (*self).on_allocation_failure(pool_id, error)
+    }
+
+    fn on_cas_retry(&self, pool_id: MemPoolId) {
+        (*self).on_cas_retry(pool_id)
+    }
+}
+
+struct TraceRing<const CAPACITY: usize> {
+    events: [Option<TraceEvent>; CAPACITY],
+    next: usize,
+    len: usize,
+    nb_overwritten: usize,
+}
+
+// Tracer keeping the last CAPACITY trace events in memory, the oldest ones being overwritten
+pub struct TraceRecorder<const CAPACITY: usize> {
+    inner: Mutex<RefCell<TraceRing<CAPACITY>>>,
+}
+
+impl<const CAPACITY: usize> TraceRecorder<CAPACITY> {
+    pub const fn new() -> TraceRecorder<CAPACITY> {
+        assert!(CAPACITY > 0, "Trace recorder capacity cannot be null");
+        TraceRecorder {
+            inner: Mutex::new(RefCell::new(TraceRing {
+                events: [None; CAPACITY],
+                next: 0,
+                len: 0,
+                nb_overwritten: 0,
+            })),
+        }
+    }
+
+    pub fn record(&self, evt: TraceEvent) {
+        interrupt::free(|cs| {
+            let mut ring = self.inner.borrow(cs).borrow_mut();
+            let next = ring.next;
+            ring.events[next] = Some(evt);
+            ring.next = (next + 1) % CAPACITY;
+            if ring.len == CAPACITY {
+                ring.nb_overwritten += 1;
+            } else {
+                ring.len += 1;
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        interrupt::free(|cs| self.inner.borrow(cs).borrow().len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of events lost since the last clear because the recorder was full
+    pub fn get_nb_overwritten(&self) -> usize {
+        interrupt::free(|cs| self.inner.borrow(cs).borrow().nb_overwritten)
+    }
+
+    // Visit the recorded events, oldest first, within a critical section
+    pub fn for_each<F: FnMut(&TraceEvent)>(&self, mut f: F) {
+        interrupt::free(|cs| {
+            let ring = self.inner.borrow(cs).borrow();
+            let oldest = (ring.next + CAPACITY - ring.len) % CAPACITY;
+            for i in 0..ring.len {
+                if let Some(evt) = &ring.events[(oldest + i) % CAPACITY] {
+                    f(evt);
+                }
+            }
+        })
+    }
+
+    pub fn clear(&self) {
+        interrupt::free(|cs| {
+            let mut ring = self.inner.borrow(cs).borrow_mut();
+            ring.len = 0;
+            ring.nb_overwritten = 0;
+        })
+    }
+}
+
+impl<const CAPACITY: usize> Default for TraceRecorder<CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CAPACITY: usize> MemoryPoolTracer for TraceRecorder<CAPACITY> {
+    fn on_allocation(&self, pool_id: MemPoolId, slot_index: SlotIndex) {
+        self.record(TraceEvent::Allocation {
+            pool_id,
+            slot_index,
+        })
+    }
+
+    fn on_free(&self, pool_id: MemPoolId, slot_index: SlotIndex) {
+        self.record(TraceEvent::Free {
+            pool_id,
+            slot_index,
+        })
+    }
+
+    fn on_allocation_failure(&self, pool_id: MemPoolId, error: SlotAllocError) {
+        self.record(TraceEvent::AllocationFailure { pool_id, error })
+    }
+
+    fn on_cas_retry(&self, pool_id: MemPoolId) {
+        self.record(TraceEvent::CasRetry { pool_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded<const CAPACITY: usize>(recorder: &TraceRecorder<CAPACITY>) -> Vec<TraceEvent> {
+        let mut events = Vec::new();
+        recorder.for_each(|evt| events.push(*evt));
+        events
+    }
+
+    #[test]
+    fn trace_recorder_test() {
+        let recorder = TraceRecorder::<3>::new();
+        assert!(recorder.is_empty());
+
+        for pool_id in 0..5 {
+            recorder.on_cas_retry(pool_id);
+        }
+        assert_eq!(recorder.len(), 3);
+        assert_eq!(recorder.get_nb_overwritten(), 2);
+        assert_eq!(
+            recorded(&recorder),
+            (2..5)
+                .map(|pool_id| TraceEvent::CasRetry { pool_id })
+                .collect::<Vec<_>>()
+        );
+
+        recorder.clear();
+        assert_eq!(recorded(&recorder), []);
+        recorder.on_free(1, 2);
+        assert_eq!(
+            recorded(&recorder),
+            [TraceEvent::Free {
+                pool_id: 1,
+                slot_index: 2
+            }]
+        );
+    }
+}
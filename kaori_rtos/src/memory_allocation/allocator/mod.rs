@@ -1,9 +1,18 @@
 use core::fmt::Debug;
+use core::time::Duration;
 
 pub mod memory_pool_allocator;
 
-
-pub trait Allocator<PointerType, FreeErrorType: Debug, AllocationErrorType: Debug>{
+pub trait Allocator<PointerType, FreeErrorType: Debug, AllocationErrorType: Debug> {
     fn allocate(&self, layout: core::alloc::Layout) -> Result<PointerType, AllocationErrorType>;
     unsafe fn free(&self, slot_pointer: PointerType) -> Result<(), FreeErrorType>;
 }
+
+// Allocators able to make the calling context wait for memory to be freed by another one
+pub trait BlockingAllocator<PointerType, AllocationErrorType: Debug> {
+    fn allocate_blocking(
+        &self,
+        layout: core::alloc::Layout,
+        timeout: Duration,
+    ) -> Result<PointerType, AllocationErrorType>;
+}
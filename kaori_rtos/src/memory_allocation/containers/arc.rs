@@ -1,103 +1,140 @@
-#[macro_export]
-macro_rules! define_arc {
-    ($arc_mod:ident, $box_mod:ident) => {
-        mod $arc_mod {
+use super::boxed::{AllocatorHandle, Box};
+use crate::memory_allocation::allocator::memory_pool_allocator::SlotPointer;
+use crate::memory_allocation::allocator::BlockingAllocator;
+use core::mem::ManuallyDrop;
+use core::time::Duration;
+use portable_atomic as atomic;
+
+#[derive(Debug)]
+struct InnerArc<T> {
+    inner: T,
+    counter: atomic::AtomicUsize,
+}
 
-            use portable_atomic as atomic;
-
-            impl<T: std::fmt::Debug> Arc<T> {
-                pub fn new(element: T) -> Arc<T> {
-                    let inner_arc = InnerArc {
-                        inner: element,
-                        counter: atomic::AtomicUsize::new(1),
-                    };
-                    let boxed_inner_arc = super::$box_mod::Box::new(inner_arc);
-
-                    Arc {
-                        inner: core::mem::ManuallyDrop::new(boxed_inner_arc),
-                        marker: core::marker::PhantomData::<T>,
-                    }
-                }
-            }
-            
-            impl<T> Drop for Arc<T> {
-                fn drop(&mut self) {
-                    unsafe {
-                        if self.inner.counter.fetch_sub(1, atomic::Ordering::Release) != 1 {
-                            return;
-                        }
-                        let _ = self.inner.counter.load(atomic::Ordering::Acquire);
-                        core::mem::ManuallyDrop::drop(&mut self.inner)
-                    }
-                }
-            }
+// Reference-counted pointer to a value stored, along with its counter, in a single slot of
+// the allocator behind `A`
+#[derive(Debug)]
+pub struct Arc<T, A: AllocatorHandle> {
+    inner: ManuallyDrop<Box<InnerArc<T>, A>>,
+}
 
-            impl<T> core::ops::Deref for Arc<T> {
-                type Target = T;
-                fn deref(&self) -> &Self::Target {
-                    let inner_box = self.inner.deref();
-                    let inner_arc = inner_box.deref();
-                    &inner_arc.inner
-                }
-            }
+impl<T> InnerArc<T> {
+    fn new(element: T) -> InnerArc<T> {
+        InnerArc {
+            inner: element,
+            counter: atomic::AtomicUsize::new(1),
+        }
+    }
+}
 
-            impl<T> core::ops::DerefMut for Arc<T> {
-                fn deref_mut(&mut self) -> &mut Self::Target {
-                    let inner_box = self.inner.deref_mut();
-                    let inner_arc = inner_box.deref_mut();
-                    &mut inner_arc.inner
-                }
-            }
+impl<T, A: AllocatorHandle> Arc<T, A> {
+    fn from_box(boxed_inner_arc: Box<InnerArc<T>, A>) -> Arc<T, A> {
+        Arc {
+            inner: ManuallyDrop::new(boxed_inner_arc),
+        }
+    }
 
-            impl<T> AsMut<T> for Arc<T> {
-                fn as_mut(&mut self) -> &mut T {
-                    <Self as core::ops::DerefMut>::deref_mut(self)
-                }
-            }
+    // Panic if no slot is available, see `try_new()`
+    pub fn new(element: T) -> Arc<T, A> {
+        Self::from_box(Box::new(InnerArc::new(element)))
+    }
 
-            impl<T> AsRef<T> for Arc<T> {
-                fn as_ref(&self) -> &T {
-                    <Self as core::ops::Deref>::deref(self)
-                }
-            }
+    // Hand the element back along with the error if no slot is available
+    pub fn try_new(element: T) -> Result<Arc<T, A>, (T, A::AllocationError)> {
+        Box::try_new(InnerArc::new(element))
+            .map(Self::from_box)
+            .map_err(|(inner_arc, err)| (inner_arc.inner, err))
+    }
 
-            #[derive(Debug)]
-            struct InnerArc<T> {
-                inner: T,
-                counter: atomic::AtomicUsize,
-            }
+    // Mutable access to the value, only granted when no other `Arc` shares it
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.inner.counter.load(atomic::Ordering::Acquire) == 1 {
+            Some(&mut this.inner.inner)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T, A: AllocatorHandle> Arc<T, A>
+where
+    A::Allocator: BlockingAllocator<SlotPointer, A::AllocationError>,
+{
+    // Wait up to `timeout` for another context to free a slot if none is available. Must not
+    // be called from an interrupt.
+    pub fn new_blocking(
+        element: T,
+        timeout: Duration,
+    ) -> Result<Arc<T, A>, (T, A::AllocationError)> {
+        Box::new_blocking(InnerArc::new(element), timeout)
+            .map(Self::from_box)
+            .map_err(|(inner_arc, err)| (inner_arc.inner, err))
+    }
+}
 
-            #[derive(Debug)]
-            pub struct Arc<T> {
-                inner: core::mem::ManuallyDrop<super::$box_mod::Box::<InnerArc<T>>>,
-                marker: core::marker::PhantomData<T>,
+// Clones handed to other contexts share the value, which is dropped by the last of them
+unsafe impl<T: Send + Sync, A: AllocatorHandle> Send for Arc<T, A> {}
+unsafe impl<T: Send + Sync, A: AllocatorHandle> Sync for Arc<T, A> {}
+
+impl<T, A: AllocatorHandle> Drop for Arc<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.inner.counter.fetch_sub(1, atomic::Ordering::Release) != 1 {
+                return;
             }
+            let _ = self.inner.counter.load(atomic::Ordering::Acquire);
+            ManuallyDrop::drop(&mut self.inner)
+        }
+    }
+}
+
+impl<T, A: AllocatorHandle> core::ops::Deref for Arc<T, A> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner.inner
+    }
+}
 
-            impl <T>Clone for Arc<T>{
-                fn clone(&self) -> Self{
-                    self.inner.counter.fetch_add(1, atomic::Ordering::Relaxed);
-                    unsafe{
-                    Arc {
-                        inner: core::mem::ManuallyDrop::new(self.inner.leak()),
-                        marker: core::marker::PhantomData::<T>,
-                    }
-                    }
-                }
+impl<T, A: AllocatorHandle> AsRef<T> for Arc<T, A> {
+    fn as_ref(&self) -> &T {
+        <Self as core::ops::Deref>::deref(self)
+    }
+}
+
+impl<T, A: AllocatorHandle> Clone for Arc<T, A> {
+    fn clone(&self) -> Self {
+        self.inner.counter.fetch_add(1, atomic::Ordering::Relaxed);
+        unsafe {
+            Arc {
+                inner: ManuallyDrop::new(self.inner.leak()),
             }
         }
+    }
+}
+
+// Declare a module holding the `Arc` allocated with the handle of a module declared by
+// `define_box!` or `define_memory_pools!`
+#[macro_export]
+macro_rules! define_arc {
+    ($arc_mod:ident, $box_mod:ident) => {
+        mod $arc_mod {
+            pub type Arc<T> =
+                $crate::memory_allocation::containers::arc::Arc<T, super::$box_mod::Handle>;
+        }
     };
 }
 
 #[cfg(test)]
 mod tests {
     use crate::memory_allocation::allocator::memory_pool_allocator::{
-        MemPoolId, MemoryPool, SlotPool,
+        get_words_per_pool, MemPoolId, MemoryPool, SlotPool,
     };
     use std::thread;
     const POOL0_ID: MemPoolId = 0;
     const POOL0_WORDS_PER_SLOT: usize = 8;
     const POOL0_SLOT_PER_POOL: usize = 30;
-    const POOL0_WORDS_PER_POOL: usize = POOL0_SLOT_PER_POOL * POOL0_WORDS_PER_SLOT;
+    const POOL0_WORDS_PER_POOL: usize =
+        get_words_per_pool(POOL0_SLOT_PER_POOL, POOL0_WORDS_PER_SLOT);
     static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
         SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
     static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
@@ -105,30 +142,70 @@ mod tests {
     define_arc!(test_arc, test_box);
 
     #[derive(Debug)]
-    struct A (u16, usize);
+    struct A(u16, usize);
 
     #[derive(Debug)]
-    struct B (test_arc::Arc<A>);
+    struct B(test_arc::Arc<A>);
 
     const A0_VAL: u16 = 0xFEE0;
     const A1_VAL: usize = 0x11ACCDF33458BC93;
 
     #[test]
     fn arc_test_0() {
-        let a = A (A0_VAL, A1_VAL);
+        let a = A(A0_VAL, A1_VAL);
         let arc_a = test_arc::Arc::new(a);
         let b = B(arc_a);
         let arc_b = test_arc::Arc::new(b);
 
-        for _ in 0..100{
+        for _ in 0..100 {
             let arc_b_clone = arc_b.clone();
-            thread::spawn( move ||{
-                assert_eq!(arc_b_clone.0.0, A0_VAL);
-                assert_eq!(arc_b_clone.0.1, A1_VAL);
+            thread::spawn(move || {
+                assert_eq!(arc_b_clone.0 .0, A0_VAL);
+                assert_eq!(arc_b_clone.0 .1, A1_VAL);
             });
         }
-        
-        assert_eq!(arc_b.0.0, A0_VAL);
-        assert_eq!(arc_b.0.1, A1_VAL);
+
+        assert_eq!(arc_b.0 .0, A0_VAL);
+        assert_eq!(arc_b.0 .1, A1_VAL);
+    }
+
+    #[test]
+    fn get_mut_test() {
+        let mut arc_a = test_arc::Arc::new(A(A0_VAL, A1_VAL));
+        test_arc::Arc::get_mut(&mut arc_a).unwrap().0 = !A0_VAL;
+        let arc_a_clone = arc_a.clone();
+        assert!(test_arc::Arc::get_mut(&mut arc_a).is_none());
+        assert_eq!(arc_a_clone.0, !A0_VAL);
+        drop(arc_a_clone);
+        assert!(test_arc::Arc::get_mut(&mut arc_a).is_some());
+    }
+
+    mod multi_pool {
+        use crate::define_memory_pools;
+        use crate::memory_allocation::allocator::memory_pool_allocator::AllocationError;
+
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+        define_memory_pools!(event_pools, [(4 * WORD_SIZE, 4), (32 * WORD_SIZE, 1)]);
+        define_arc!(event_arc, event_pools);
+
+        #[test]
+        fn multi_pool_arc_test() {
+            let small = event_arc::Arc::new(0x5Au8);
+            let large = event_arc::Arc::new([0xA5u8; 16 * WORD_SIZE]);
+            let large_clone = large.clone();
+            assert_eq!(event_pools::ALLOCATOR.get_pool_stats(0).nb_free_slots, 3);
+            assert_eq!(event_pools::ALLOCATOR.get_pool_stats(1).nb_free_slots, 0);
+
+            let (payload, err) = event_arc::Arc::try_new([0u8; 16 * WORD_SIZE]).unwrap_err();
+            assert_eq!(payload, [0u8; 16 * WORD_SIZE]);
+            assert_eq!(err, AllocationError::NoMemoryAvailable);
+
+            assert_eq!(*small, 0x5A);
+            drop(large);
+            assert!(large_clone.iter().all(|byte| *byte == 0xA5));
+            drop(large_clone);
+            drop(small);
+            assert_eq!(event_pools::ALLOCATOR.get_stats().nb_free_slots, 5);
+        }
     }
 }
@@ -1,103 +1,205 @@
-#[macro_export]
-macro_rules! define_box {
-    ($box_mod:ident, $allocator_instance: ident) => {
-        mod $box_mod{
-        use crate::memory_allocation::allocator::memory_pool_allocator::SlotPointer;
-        impl<T> Box<T> {
-            pub fn new(element: T) -> Box<T> {
-                unsafe {
-                    let slot_pointer = super::$allocator_instance
-                        .allocate(core::alloc::Layout::new::<T>())
-                        .unwrap();
-                    let allocated_mem = super::$allocator_instance
-                        .get_slot_transmute(&slot_pointer)
-                        .unwrap();
-
-                    allocated_mem.write(element);
-
-                    Box {
-                        inner: slot_pointer,
-                        marker: core::marker::PhantomData::default(),
-                    }
-                }
-            }
+use crate::memory_allocation::allocator::memory_pool_allocator::{MemoryAccessor, SlotPointer};
+use crate::memory_allocation::allocator::{Allocator, BlockingAllocator};
+use core::fmt::Debug;
+use core::marker::PhantomData;
+use core::time::Duration;
 
-            pub unsafe fn leak(&self) -> Self{
-                Self{
-                    inner: self.inner,
-                    marker: core::marker::PhantomData::default()
-                }
-            }
-        }
+// Zero-sized type standing for a statically allocated allocator, so that boxes only have to
+// store their slot pointer
+pub trait AllocatorHandle: 'static {
+    type FreeError: Debug;
+    type AllocationError: Debug;
+    type Allocator: Allocator<SlotPointer, Self::FreeError, Self::AllocationError>
+        + MemoryAccessor<SlotPointer>
+        + 'static;
 
-        impl<T> Drop for Box<T> {
-            fn drop(&mut self) {
-                unsafe {
-                    let allocated_mem: &mut std::mem::MaybeUninit<T> =
-                        super::$allocator_instance.get_slot_transmute(&self.inner).unwrap();
-                    allocated_mem.assume_init_drop();
-                    super::$allocator_instance.free(self.inner).unwrap();
-                }
-            }
+    fn get_allocator() -> &'static Self::Allocator;
+}
+
+// Owning pointer to a value stored in a slot of the allocator behind `A`. A box is a single
+// word, except on 32-bit targets with the "wide-slot-pointer" feature, whose 64-bit slot
+// pointers take two words.
+#[derive(Debug)]
+pub struct Box<T, A: AllocatorHandle> {
+    inner: SlotPointer,
+    marker: PhantomData<(T, A)>,
+}
+
+impl<T, A: AllocatorHandle> Box<T, A> {
+    // Panic if no slot is available, see `try_new()`
+    pub fn new(element: T) -> Box<T, A> {
+        match Self::try_new(element) {
+            Ok(boxed) => boxed,
+            Err((_, err)) => panic!("Box allocation failed: {:?}", err),
         }
+    }
 
-        impl<T> core::ops::Deref for Box<T> {
-            type Target = T;
-            fn deref(&self) -> &Self::Target {
-                unsafe {
-                    let allocated_mem =
-                        super::$allocator_instance.get_slot_transmute(&self.inner).unwrap();
-                    allocated_mem.assume_init_ref()
-                }
-            }
+    // Hand the element back along with the error if no slot is available
+    pub fn try_new(element: T) -> Result<Box<T, A>, (T, A::AllocationError)> {
+        match A::get_allocator().allocate(core::alloc::Layout::new::<T>()) {
+            Ok(slot_pointer) => Ok(Self::from_slot(slot_pointer, element)),
+            Err(err) => Err((element, err)),
         }
+    }
 
-        impl<T> core::ops::DerefMut for Box<T> {
-            fn deref_mut(&mut self) -> &mut Self::Target {
-                unsafe {
-                    let allocated_mem =
-                        super::$allocator_instance.get_slot_transmute(&self.inner).unwrap();
-                    allocated_mem.assume_init_mut()
-                }
-            }
+    fn from_slot(slot_pointer: SlotPointer, element: T) -> Box<T, A> {
+        unsafe {
+            Self::get_slot_ptr(&slot_pointer).write(element);
         }
+        Box {
+            inner: slot_pointer,
+            marker: PhantomData,
+        }
+    }
 
-        impl <T> AsMut<T> for Box<T> {
-            fn as_mut(&mut self) -> &mut T {
-                <Self as core::ops::DerefMut>::deref_mut(self)    
-            }
+    // Duplicate the box without taking ownership of its value, which must only be dropped
+    // through one of the copies
+    pub unsafe fn leak(&self) -> Self {
+        Self {
+            inner: self.inner,
+            marker: PhantomData,
         }
+    }
 
-        impl <T> AsRef<T> for Box<T> {
-            fn as_ref(&self) -> &T {
-                <Self as core::ops::Deref>::deref(self)    
-            }
+    fn get_slot_ptr(slot_pointer: &SlotPointer) -> *mut T {
+        A::get_allocator().get_slot_mut(slot_pointer).unwrap() as *mut T
+    }
+}
+
+impl<T, A: AllocatorHandle> Box<T, A>
+where
+    A::Allocator: BlockingAllocator<SlotPointer, A::AllocationError>,
+{
+    // Wait up to `timeout` for another context to free a slot if none is available. Must not
+    // be called from an interrupt.
+    pub fn new_blocking(
+        element: T,
+        timeout: Duration,
+    ) -> Result<Box<T, A>, (T, A::AllocationError)> {
+        match A::get_allocator().allocate_blocking(core::alloc::Layout::new::<T>(), timeout) {
+            Ok(slot_pointer) => Ok(Self::from_slot(slot_pointer, element)),
+            Err(err) => Err((element, err)),
         }
+    }
+}
 
-        #[derive(Debug)]
-        pub struct Box<T> {
-            inner: SlotPointer,
-            marker: core::marker::PhantomData<T>,
+impl<T, A: AllocatorHandle> Drop for Box<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            Self::get_slot_ptr(&self.inner).drop_in_place();
+            // Freeing a slot owned by a box can only fail if the pool has been corrupted
+            let freed = A::get_allocator().free(self.inner);
+            debug_assert!(freed.is_ok(), "Box free failed: {:?}", freed);
         }
     }
+}
+
+impl<T, A: AllocatorHandle> core::ops::Deref for Box<T, A> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::get_slot_ptr(&self.inner) }
+    }
+}
+
+impl<T, A: AllocatorHandle> core::ops::DerefMut for Box<T, A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *Self::get_slot_ptr(&self.inner) }
+    }
+}
+
+impl<T, A: AllocatorHandle> AsMut<T> for Box<T, A> {
+    fn as_mut(&mut self) -> &mut T {
+        <Self as core::ops::DerefMut>::deref_mut(self)
+    }
+}
+
+impl<T, A: AllocatorHandle> AsRef<T> for Box<T, A> {
+    fn as_ref(&self) -> &T {
+        <Self as core::ops::Deref>::deref(self)
+    }
+}
+
+// Declare a module holding the handle of an allocator static and the `Box` it backs. The
+// allocator is either a `MemoryPool`, or a `MemoryPoolAllocator` when its type is given.
+#[macro_export]
+macro_rules! define_box {
+    ($box_mod:ident, $allocator_instance:ident) => {
+        mod $box_mod {
+            #[allow(unused_imports)]
+            use super::*;
+            $crate::define_box!(@handle $allocator_instance: MemoryPool);
+        }
+    };
+    ($box_mod:ident, $allocator_instance:ident: $allocator_type:ident) => {
+        mod $box_mod {
+            #[allow(unused_imports)]
+            use super::*;
+            $crate::define_box!(@handle $allocator_instance: $allocator_type);
+        }
+    };
+    (@handle $allocator_instance:ident: MemoryPool) => {
+        $crate::define_box!(@handle $allocator_instance: MemoryPool,
+            SlotFreeingError, SlotAllocError);
+    };
+    (@handle $allocator_instance:ident: MemoryPoolAllocator) => {
+        $crate::define_box!(@handle $allocator_instance: MemoryPoolAllocator,
+            FreeError, AllocationError);
+    };
+    (@handle $allocator_instance:ident: $allocator_type:ident,
+        $free_error:ident, $allocation_error:ident) => {
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct Handle;
+
+        impl $crate::memory_allocation::containers::boxed::AllocatorHandle for Handle {
+            type FreeError =
+                $crate::memory_allocation::allocator::memory_pool_allocator::$free_error;
+            type AllocationError =
+                $crate::memory_allocation::allocator::memory_pool_allocator::$allocation_error;
+            type Allocator =
+                $crate::memory_allocation::allocator::memory_pool_allocator::$allocator_type<
+                    'static,
+                >;
+
+            fn get_allocator() -> &'static Self::Allocator {
+                &$allocator_instance
+            }
+        }
+
+        pub type Box<T> = $crate::memory_allocation::containers::boxed::Box<T, Handle>;
     };
 }
+
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::memory_allocation::allocator::memory_pool_allocator::{
-        MemPoolId, MemoryPool, SlotPointer, SlotPool,
+        get_words_per_pool, MemPoolId, MemoryPool, SlotAllocError, SlotFreeingError, SlotPool,
     };
 
     use std::{sync::mpsc, thread};
     const POOL0_ID: MemPoolId = 0;
     const POOL0_WORDS_PER_SLOT: usize = 8;
     const POOL0_SLOT_PER_POOL: usize = 30;
-    const POOL0_WORDS_PER_POOL: usize = POOL0_SLOT_PER_POOL * POOL0_WORDS_PER_SLOT;
+    const POOL0_WORDS_PER_POOL: usize =
+        get_words_per_pool(POOL0_SLOT_PER_POOL, POOL0_WORDS_PER_SLOT);
     static STATIC_MEMORY_POOL: SlotPool<POOL0_WORDS_PER_POOL> =
         SlotPool::<POOL0_WORDS_PER_POOL>::new(POOL0_WORDS_PER_SLOT, POOL0_ID);
     static MEMORY_POOL_0: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
 
-    define_box!(Test, MEMORY_POOL_0);
+    #[derive(Debug)]
+    struct Pool0;
+
+    impl AllocatorHandle for Pool0 {
+        type FreeError = SlotFreeingError;
+        type AllocationError = SlotAllocError;
+        type Allocator = MemoryPool<'static>;
+
+        fn get_allocator() -> &'static MemoryPool<'static> {
+            &MEMORY_POOL_0
+        }
+    }
+
+    define_box!(test_box, MEMORY_POOL_0);
 
     #[derive(Debug)]
     struct A {
@@ -118,9 +220,9 @@ mod tests {
 
     #[derive(Debug)]
     enum UserEvent {
-        A(Test::Box<A>),
-        B(Test::Box<B>),
-        C(Test::Box<C>),
+        A(Box<A, Pool0>),
+        B(Box<B, Pool0>),
+        C(Box<C, Pool0>),
     }
     const A_VAL: u8 = 10;
     const B0_VAL: usize = 0xFEE0ACB8CDA24E2C;
@@ -128,36 +230,56 @@ mod tests {
     const C0_VAL: usize = 0x11ACCDF33458BC93;
     const C1_VAL: u16 = 0x652E;
 
-    fn handle_event(evt_box: Test::Box<UserEvent>) {
-        println!("B");
+    // Boxes from any allocator can be handled by the same code
+    fn handle_event<H: AllocatorHandle>(evt_box: Box<UserEvent, H>) {
         let i = evt_box.as_ref();
         match i {
             UserEvent::A(a) => {
                 assert_eq!(a.a, A_VAL);
             }
-            UserEvent::B(b) =>{
+            UserEvent::B(b) => {
                 assert_eq!(b.b0, B0_VAL);
                 assert_eq!(b.b1, B1_VAL);
             }
-            UserEvent::C(c) =>{
+            UserEvent::C(c) => {
                 assert_eq!(c.c0, C0_VAL);
                 assert_eq!(c.c1, C1_VAL);
             }
         }
     }
+
     #[test]
     fn evt_box_test_0() {
-        let evt_a = Test::Box::new(A { a: A_VAL });
+        let box_words = if cfg!(all(
+            feature = "wide-slot-pointer",
+            target_pointer_width = "32"
+        )) {
+            2
+        } else {
+            1
+        };
+        assert_eq!(
+            core::mem::size_of::<Box<UserEvent, Pool0>>(),
+            box_words * core::mem::size_of::<usize>()
+        );
+
+        let evt_a = Box::new(A { a: A_VAL });
         let evt_a = UserEvent::A(evt_a);
-        let evt_a = Test::Box::new(evt_a);
+        let evt_a = Box::<_, Pool0>::new(evt_a);
 
-        let evt_b = Test::Box::new(B { b0: B0_VAL, b1: B1_VAL});
+        let evt_b = Box::new(B {
+            b0: B0_VAL,
+            b1: B1_VAL,
+        });
         let evt_b = UserEvent::B(evt_b);
-        let evt_b = Test::Box::new(evt_b);
- 
-        let evt_c = Test::Box::new(C { c0: C0_VAL, c1: C1_VAL});
+        let evt_b = Box::<_, Pool0>::new(evt_b);
+
+        let evt_c = Box::new(C {
+            c0: C0_VAL,
+            c1: C1_VAL,
+        });
         let evt_c = UserEvent::C(evt_c);
-        let evt_c = Test::Box::new(evt_c);
+        let evt_c = Box::<_, Pool0>::new(evt_c);
 
         let (tx, rx) = mpsc::channel();
         tx.send(evt_a).unwrap();
@@ -165,13 +287,119 @@ mod tests {
         tx.send(evt_c).unwrap();
 
         thread::spawn(move || {
-            for _ in 0..3{
+            for _ in 0..3 {
                 let evt = rx.recv().unwrap();
-                println!("{:?}", *evt);
                 handle_event(evt);
             }
         })
         .join()
         .unwrap();
+
+        // Same pool, other handle type declared by the macro
+        let mut evt = test_box::Box::new(UserEvent::A(Box::new(A { a: 0 })));
+        if let UserEvent::A(a) = evt.as_mut() {
+            a.a = A_VAL;
+        }
+        handle_event(evt);
+        assert_eq!(MEMORY_POOL_0.get_stats().nb_free_slots, POOL0_SLOT_PER_POOL);
+    }
+
+    mod multi_pool {
+        use crate::define_memory_pools;
+        use crate::memory_allocation::allocator::memory_pool_allocator::{
+            MemoryPool, MemoryPoolAllocator,
+        };
+
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+        define_memory_pools!(
+            event_pools,
+            [(WORD_SIZE, 2), (4 * WORD_SIZE, 3), (16 * WORD_SIZE, 1)]
+        );
+
+        static MEMORY_POOLS: [&MemoryPool; 2] =
+            [event_pools::MEMORY_POOLS[0], event_pools::MEMORY_POOLS[1]];
+        static SMALL_ALLOCATOR: MemoryPoolAllocator = MemoryPoolAllocator::new(&MEMORY_POOLS);
+        define_box!(small_box, SMALL_ALLOCATOR: MemoryPoolAllocator);
+
+        type Frame = [u8; 12 * WORD_SIZE];
+
+        // Events fit in the middle pool, their payloads in the pool fitting them best
+        #[derive(Debug)]
+        enum Evt {
+            Tick { count: usize },
+            Sample(event_pools::Box<[u16; 4]>),
+            Frame(event_pools::Box<Frame>),
+        }
+
+        fn get_nb_free_slots() -> [usize; 3] {
+            core::array::from_fn(|i| event_pools::MEMORY_POOLS[i].get_stats().nb_free_slots)
+        }
+
+        #[test]
+        fn multi_pool_box_test() {
+            let tick = event_pools::Box::new(Evt::Tick { count: 1 });
+            let sample_payload = event_pools::Box::new([1, 2, 3, 4]);
+            assert_eq!(sample_payload.inner.get_mem_pool_id(), 0);
+            let sample = event_pools::Box::new(Evt::Sample(sample_payload));
+            let frame_payload = event_pools::Box::new([0xAB; 12 * WORD_SIZE]);
+            assert_eq!(frame_payload.inner.get_mem_pool_id(), 2);
+            let frame = event_pools::Box::new(Evt::Frame(frame_payload));
+            assert_eq!(tick.inner.get_mem_pool_id(), 1);
+            assert_eq!(sample.inner.get_mem_pool_id(), 1);
+            assert_eq!(get_nb_free_slots(), [1, 0, 0]);
+
+            assert!(matches!(*tick, Evt::Tick { count: 1 }));
+            if let Evt::Sample(sample) = &*sample {
+                assert_eq!(**sample, [1, 2, 3, 4]);
+            }
+            if let Evt::Frame(frame) = &*frame {
+                assert!(frame.iter().all(|byte| *byte == 0xAB));
+            }
+
+            // Slots are freed back to the pool they were allocated from
+            drop(sample);
+            assert_eq!(get_nb_free_slots(), [2, 1, 0]);
+            drop(frame);
+            drop(tick);
+            assert_eq!(get_nb_free_slots(), [2, 3, 1]);
+
+            let small = small_box::Box::new(Evt::Tick { count: 2 });
+            assert_eq!(small.inner.get_mem_pool_id(), 1);
+            drop(small);
+            assert_eq!(get_nb_free_slots(), [2, 3, 1]);
+        }
+    }
+
+    mod blocking {
+        use super::*;
+        use crate::memory_allocation::allocator::memory_pool_allocator::SlotAllocError;
+        use std::time::{Duration, Instant};
+
+        static STATIC_MEMORY_POOL: SlotPool<{ get_words_per_pool(1, 2) }> = SlotPool::new(2, 0);
+        static MEMORY_POOL: MemoryPool = MemoryPool::from(&STATIC_MEMORY_POOL);
+        define_box!(blocking_box, MEMORY_POOL);
+
+        #[test]
+        fn box_try_new_and_blocking_test() {
+            let first = blocking_box::Box::try_new(1usize).unwrap();
+            let (element, err) = blocking_box::Box::try_new(2usize).unwrap_err();
+            assert_eq!((element, err), (2, SlotAllocError::PoolFull));
+
+            let start = Instant::now();
+            let (element, err) =
+                blocking_box::Box::new_blocking(3usize, Duration::from_millis(20)).unwrap_err();
+            assert_eq!((element, err), (3, SlotAllocError::PoolFull));
+            assert!(start.elapsed() >= Duration::from_millis(20));
+            assert_eq!(MEMORY_POOL.get_wait_list().get_nb_waiters(), 0);
+
+            // Woken up by another context freeing the slot
+            let freeing_thread = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                drop(first);
+            });
+            let second = blocking_box::Box::new_blocking(4usize, Duration::MAX).unwrap();
+            assert_eq!(*second, 4);
+            freeing_thread.join().unwrap();
+        }
     }
 }
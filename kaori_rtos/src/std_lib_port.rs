@@ -12,19 +12,52 @@ impl CriticalSection {
     }
 }
 
-pub mod interrupt{
+// Critical sections are simulated with a process-wide lock, reentrant within a thread, so that
+// threads standing for interrupts (e.g. a tick thread) are excluded as they would be by masking.
+pub mod interrupt {
+    use std::cell::{Cell, RefCell};
+    use std::sync::{Mutex, MutexGuard};
 
+    static CRITICAL_SECTION_LOCK: Mutex<()> = Mutex::new(());
 
-#[inline]
-pub fn free<F, R>(f: F) -> R
-where
-    F: FnOnce(&super::CriticalSection) -> R,
-{
+    thread_local! {
+        static NESTING: Cell<usize> = const { Cell::new(0) };
+        static LOCK_GUARD: RefCell<Option<MutexGuard<'static, ()>>> = const { RefCell::new(None) };
+    }
 
-    let r = f(unsafe { &super::CriticalSection::new() });
+    struct NestingGuard;
 
-    r
-}
+    impl NestingGuard {
+        fn enter() -> NestingGuard {
+            if NESTING.get() == 0 {
+                let lock_guard = CRITICAL_SECTION_LOCK
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                LOCK_GUARD.with_borrow_mut(|guard| *guard = Some(lock_guard));
+            }
+            NESTING.set(NESTING.get() + 1);
+            NestingGuard
+        }
+    }
+
+    impl Drop for NestingGuard {
+        fn drop(&mut self) {
+            let nesting = NESTING.get() - 1;
+            NESTING.set(nesting);
+            if nesting == 0 {
+                LOCK_GUARD.with_borrow_mut(|guard| *guard = None);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn free<F, R>(f: F) -> R
+    where
+        F: FnOnce(&super::CriticalSection) -> R,
+    {
+        let _nesting_guard = NestingGuard::enter();
+        f(unsafe { &super::CriticalSection::new() })
+    }
 }
 
 use std::cell::UnsafeCell;
@@ -49,8 +82,130 @@ impl<T> Mutex<T> {
     }
 }
 
-
 // NOTE A `Mutex` can be used as a channel so the protected data must be `Send`
 // to prevent sending non-Sendable stuff (e.g. access tokens) across different
 // execution contexts (e.g. interrupts)
 unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+// Preemption is simulated on the calling thread: interrupts are closures run through
+// `isr_entry()`/`isr_exit()` and a pended scheduler request is serviced on return from
+// the outermost one.
+pub mod scheduler {
+    use std::cell::Cell;
+
+    thread_local! {
+        static ISR_NEST: Cell<usize> = const { Cell::new(0) };
+        static SCHEDULER_PENDED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub fn pend() {
+        SCHEDULER_PENDED.set(true);
+    }
+
+    pub fn in_isr() -> bool {
+        ISR_NEST.get() > 0
+    }
+
+    pub fn isr_entry() {
+        ISR_NEST.set(ISR_NEST.get() + 1);
+    }
+
+    // Return `true` if the scheduler has been pended and must run now that the outermost
+    // interrupt returns
+    pub fn isr_exit() -> bool {
+        let isr_nest = ISR_NEST.get() - 1;
+        ISR_NEST.set(isr_nest);
+        isr_nest == 0 && SCHEDULER_PENDED.replace(false)
+    }
+}
+
+pub mod tick {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    // Thread calling `on_tick` periodically, standing for the SysTick interrupt. Only the QV
+    // kernel is supported: the pended scheduler request of QK is local to the thread posting
+    // from the interrupt, so the active objects a tick makes ready would not be activated. With
+    // QK, tick from the scheduler thread with `QkKernel::simulate_isr()` instead.
+    pub struct TickThread {
+        running: Arc<AtomicBool>,
+        join_handle: JoinHandle<()>,
+    }
+
+    impl TickThread {
+        pub fn spawn<F: Fn() + Send + 'static>(period: Duration, on_tick: F) -> TickThread {
+            let running = Arc::new(AtomicBool::new(true));
+            let thread_running = running.clone();
+            let join_handle = thread::spawn(move || {
+                while thread_running.load(Ordering::Relaxed) {
+                    thread::sleep(period);
+                    on_tick();
+                }
+            });
+            TickThread {
+                running,
+                join_handle,
+            }
+        }
+
+        pub fn stop(self) {
+            self.running.store(false, Ordering::Relaxed);
+            self.join_handle.join().unwrap();
+        }
+    }
+}
+
+// Contexts waiting for a resource sleep on a process-wide condition variable, every release
+// waking all of them up to check whether it concerns them
+pub mod wait {
+    use std::sync::{Condvar, Mutex};
+    use std::time::{Duration, Instant};
+
+    static WAIT_LOCK: Mutex<()> = Mutex::new(());
+    static WAIT_CONDVAR: Condvar = Condvar::new();
+
+    pub struct Deadline {
+        instant: Option<Instant>,
+    }
+
+    impl Deadline {
+        pub fn after(timeout: Duration) -> Deadline {
+            Deadline {
+                instant: Instant::now().checked_add(timeout),
+            }
+        }
+    }
+
+    pub fn notify_all() {
+        let _guard = WAIT_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        WAIT_CONDVAR.notify_all();
+    }
+
+    // Sleep until `woken` returns `true`, checked after each notification, or the deadline
+    // passes. Return `false` on timeout.
+    pub fn wait_until<F: Fn() -> bool>(deadline: &mut Deadline, woken: F) -> bool {
+        let mut guard = WAIT_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        loop {
+            if woken() {
+                return true;
+            }
+            let timeout = match deadline.instant {
+                Some(instant) => match instant.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => timeout,
+                    _ => return false,
+                },
+                None => Duration::MAX,
+            };
+            guard = WAIT_CONDVAR
+                .wait_timeout(guard, timeout)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+}
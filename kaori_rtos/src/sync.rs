@@ -1,78 +1,142 @@
-pub use core::borrow::Borrow;
+use crate::port;
 use core::cell::UnsafeCell;
 use core::marker::PhantomData;
-use core::ops::{Deref, DerefMut};
-use core::ptr::NonNull;
-use core::sync::atomic::{self, AtomicBool};
+use core::mem::MaybeUninit;
+use core::time::Duration;
+use portable_atomic as atomic;
 
-pub struct AsyncArrayCell<T, const SIZE : usize>{
-    inner: UnsafeCell<[T; SIZE ]>
+// Array shared between contexts each owning some of its elements, such as the storage of a
+// memory pool whose slots are handed out to different contexts. The elements are only accessed
+// through raw pointers, so that no reference to the whole array aliases the owned elements.
+pub struct AsyncArrayCell<T, const SIZE: usize> {
+    inner: [UnsafeCell<MaybeUninit<T>>; SIZE],
 }
 
-impl <T, const SIZE : usize>AsyncArrayCell<T, SIZE>{
-    pub const fn new(inner: [T; SIZE]) -> AsyncArrayCell<T, SIZE>{
-        AsyncArrayCell{inner: UnsafeCell::new(inner)}
+impl<T: Copy, const SIZE: usize> AsyncArrayCell<T, SIZE> {
+    pub const fn new(inner: [T; SIZE]) -> AsyncArrayCell<T, SIZE> {
+        let mut cells = [const { UnsafeCell::new(MaybeUninit::uninit()) }; SIZE];
+        let mut index = 0;
+        while index < SIZE {
+            cells[index] = UnsafeCell::new(MaybeUninit::new(inner[index]));
+            index += 1;
+        }
+        AsyncArrayCell { inner: cells }
     }
-    pub const fn get<'cs>(&'cs self) -> *mut [T] {
-        self.inner.get()
+}
+
+impl<T, const SIZE: usize> AsyncArrayCell<T, SIZE> {
+    pub const fn get(&self) -> *mut T {
+        UnsafeCell::raw_get(self.inner.as_ptr()) as *mut T
     }
-    pub const fn borrow_mut(&self) -> AsyncArrayCellRef<T> {
-        AsyncArrayCellRef{inner: self.inner.get(), marker: PhantomData} 
+    pub const fn borrow_mut(&self) -> AsyncArrayCellRef<'_, T> {
+        AsyncArrayCellRef {
+            inner: self.inner.as_slice(),
+            marker: PhantomData,
+        }
     }
 }
 
-unsafe impl <T, const SIZE : usize > Sync for AsyncArrayCell<T, SIZE>{
-}
+unsafe impl<T, const SIZE: usize> Sync for AsyncArrayCell<T, SIZE> {}
 
-pub struct AsyncArrayCellRef<'a, T>{
-    inner: *mut [T],
+// Borrow of an `AsyncArrayCell`, or of a memory region handed over at runtime
+pub struct AsyncArrayCellRef<'a, T> {
+    inner: *const [UnsafeCell<MaybeUninit<T>>],
     marker: PhantomData<&'a mut T>,
 }
 
-impl <'a, T> AsyncArrayCellRef<'a, T> {
-    pub const unsafe fn deref_mut(&self) -> &mut [T] {
-        unsafe{
-            &mut *self.inner
+impl<'a, T> AsyncArrayCellRef<'a, T> {
+    pub fn from_mut_slice(inner: &'a mut [MaybeUninit<T>]) -> AsyncArrayCellRef<'a, T> {
+        // `UnsafeCell` has the same layout as the value it wraps
+        AsyncArrayCellRef {
+            inner: inner as *mut [MaybeUninit<T>] as *const [UnsafeCell<MaybeUninit<T>>],
+            marker: PhantomData,
         }
     }
+
+    pub const fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    // Pointer to the first element, valid for writes to the whole array
+    pub const fn as_ptr(&self) -> *mut T {
+        UnsafeCell::raw_get(self.inner as *const UnsafeCell<MaybeUninit<T>>) as *mut T
+    }
 }
 
-unsafe impl <'a, T> Sync for AsyncArrayCellRef<'a, T>{
+unsafe impl<'a, T> Sync for AsyncArrayCellRef<'a, T> {}
+
+// Lets pools built at runtime be moved to their static home
+unsafe impl<'a, T: Send> Send for AsyncArrayCellRef<'a, T> {}
+
+// Contexts waiting for a resource to be released, which must notify the list on each release
+pub struct WaitList {
+    nb_waiters: atomic::AtomicUsize,
+    // Incremented on each release, so that waiters detect releases that occurred while they
+    // were trying to acquire the resource
+    generation: atomic::AtomicUsize,
 }
 
-impl <'a, T>Deref for AsyncArrayCellRef<'a, T> {
-    type Target = [T];
-    fn deref(&self) -> &Self::Target {
-        unsafe{
-           self.inner.as_ref().unwrap() 
+impl WaitList {
+    pub const fn new() -> WaitList {
+        WaitList {
+            nb_waiters: atomic::AtomicUsize::new(0),
+            generation: atomic::AtomicUsize::new(0),
         }
     }
-}
 
-// impl <'a, T>DerefMut for AsyncArrayCellRef<'a, T> {
-//     fn deref_mut(&mut self) -> &mut Self::Target {
-//         unsafe{
-//             self.inner.as_mut().unwrap()
-//         }
-//     }
-// }
-
-// unsafe impl <T: Send>Sync for AsyncRefCell<T>{
-// }
-
-// impl <T>AsyncRefCell<T>{
-//     pub const fn new(val: T) -> AsyncRefCell<T>{
-//         AsyncRefCell{inner :UnsafeCell::new(val), locked: AtomicBool::new(false)}
-//     }
-
-//     pub const fn borrow_mut(&self) -> &mut T{
-//         unsafe{
-//             // if self.locked.compare_exchange(false, true, atomic::Ordering::Acquire, atomic::Ordering::Relaxed).is_err(){
-//             //   panic!("Already mutably borrowed")
-//             // };
-
-//             &mut *self.inner.get()
-//         }
-//     }
-// }
+    pub fn notify(&self) {
+        self.generation.fetch_add(1, atomic::Ordering::SeqCst);
+        if self.nb_waiters.load(atomic::Ordering::SeqCst) != 0 {
+            port::wait::notify_all();
+        }
+    }
+
+    pub fn get_nb_waiters(&self) -> usize {
+        self.nb_waiters.load(atomic::Ordering::SeqCst)
+    }
+
+    // Call `try_acquire` until it succeeds, waiting for a notification of one of the lists
+    // between two attempts. Return the result of the last attempt once the timeout elapsed.
+    // Must not be called from an interrupt.
+    pub fn wait_any<'a, I, R, F>(wait_lists: I, timeout: Duration, mut try_acquire: F) -> Option<R>
+    where
+        I: Iterator<Item = &'a WaitList> + Clone,
+        F: FnMut() -> Option<R>,
+    {
+        assert!(!port::scheduler::in_isr(), "Cannot wait from an interrupt");
+        let get_generation = || {
+            wait_lists.clone().fold(0usize, |generation, wait_list| {
+                generation.wrapping_add(wait_list.generation.load(atomic::Ordering::SeqCst))
+            })
+        };
+        let mut deadline = port::wait::Deadline::after(timeout);
+        loop {
+            let generation = get_generation();
+            if let Some(resource) = try_acquire() {
+                return Some(resource);
+            }
+            // Registering before checking the generation again, so that a release occurring
+            // in between either is seen here or notifies the port
+            wait_lists.clone().for_each(|wait_list| {
+                wait_list.nb_waiters.fetch_add(1, atomic::Ordering::SeqCst);
+            });
+            let notified = port::wait::wait_until(&mut deadline, || get_generation() != generation);
+            wait_lists.clone().for_each(|wait_list| {
+                wait_list.nb_waiters.fetch_sub(1, atomic::Ordering::SeqCst);
+            });
+            if !notified {
+                return try_acquire();
+            }
+        }
+    }
+
+    pub fn wait<R, F: FnMut() -> Option<R>>(&self, timeout: Duration, try_acquire: F) -> Option<R> {
+        Self::wait_any(core::iter::once(self), timeout, try_acquire)
+    }
+}
 
+impl Default for WaitList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,32 @@
+// Atomics of the lock-free primitives. The model tests swap them for the ones of
+// `utils::model`, so that its explorer can interleave the threads at each atomic operation.
+// They only build with `RUSTFLAGS="--cfg kaori_model" cargo test`, the other tests running the
+// atomics of `portable_atomic` like the rest of the builds.
+#[cfg(not(all(test, kaori_model)))]
+#[allow(unused_imports)]
+pub use portable_atomic::{AtomicPtr, AtomicU32, AtomicUsize};
+
+#[cfg(all(test, kaori_model))]
+#[allow(unused_imports)]
+pub use super::model::atomic::{AtomicPtr, AtomicU32, AtomicUsize};
+
+// Double-word atomics of the tagged CAS of `treiber::Stack`, on the targets without LL/SC
+#[cfg(all(
+    not(all(test, kaori_model)),
+    target_has_atomic = "ptr",
+    not(all(target_arch = "arm", target_has_atomic = "32"))
+))]
+#[allow(unused_imports)]
+pub use portable_atomic::{AtomicU128, AtomicU64};
+
+#[cfg(all(
+    test,
+    kaori_model,
+    target_has_atomic = "ptr",
+    not(all(target_arch = "arm", target_has_atomic = "32"))
+))]
+#[allow(unused_imports)]
+pub use super::model::atomic::{AtomicU128, AtomicU64};
+
+#[allow(unused_imports)]
+pub use portable_atomic::Ordering;
@@ -0,0 +1,31 @@
+// Exclusive load and store instructions of the ARM cores, shared by the lock-free primitives
+use core::arch::asm;
+
+#[inline(always)]
+pub fn clear_load_link() {
+    unsafe { asm!("clrex", options(nomem, nostack)) }
+}
+
+/// # Safety
+///
+/// - `addr` must be a valid pointer.
+#[inline(always)]
+pub unsafe fn load_link(addr: *const usize) -> usize {
+    let value;
+    asm!("ldrex {}, [{}]", out(reg) value, in(reg) addr, options(nostack));
+    value
+}
+
+/// # Safety
+///
+/// - `addr` must be a valid pointer.
+#[inline(always)]
+pub unsafe fn store_conditional(value: usize, addr: *mut usize) -> Result<(), ()> {
+    let outcome: usize;
+    asm!("strex {}, {}, [{}]", out(reg) outcome, in(reg) value, in(reg) addr, options(nostack));
+    if outcome == 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
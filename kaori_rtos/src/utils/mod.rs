@@ -1,3 +1,12 @@
-mod treiber;
-
+// Atomics of the lock-free primitives, and the interleaving explorer testing them
+pub(crate) mod atomic;
+#[cfg(test)]
+pub(crate) mod model;
 
+// Intrusive lock-free primitives, on the targets having CAS
+#[cfg(all(target_arch = "arm", target_has_atomic = "32"))]
+mod llsc;
+#[cfg(target_has_atomic = "ptr")]
+pub(crate) mod mpsc;
+#[cfg(target_has_atomic = "ptr")]
+pub(crate) mod treiber;
@@ -0,0 +1,448 @@
+// Exhaustive interleaving explorer for the tests of the lock-free primitives.
+//
+// `check()` runs a test closure once per interleaving of the threads the closure starts with
+// `threads()`. The threads are real ones, but only one of them runs at a time: each operation on
+// the atomics of `model::atomic`, which replace the ones of `utils::atomic` in the tests, hands
+// over to the thread chosen by the explorer. The choices are explored depth first, each
+// execution replaying the choices of the previous one up to the last one having an alternative
+// left.
+//
+// Only sequentially consistent executions are explored, weaker memory orderings being out of
+// scope. The preemptions of an execution, i.e. the switches away from a thread which could have
+// kept running, are bounded, as most concurrency bugs only need a few of them.
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+pub const DEFAULT_MAX_PREEMPTIONS: usize = 2;
+
+// Thread chosen at a scheduling point, and the ones left to explore instead
+struct Choice {
+    chosen: usize,
+    alternatives: Vec<usize>,
+}
+
+struct Explorer {
+    path: Vec<Choice>,
+    max_preemptions: usize,
+}
+
+struct State {
+    active: usize,
+    finished: Vec<bool>,
+    path: Vec<Choice>,
+    position: usize,
+    nb_preemptions: usize,
+    max_preemptions: usize,
+    // Set when a thread panicked, the other ones unwinding instead of waiting for their turn
+    aborted: bool,
+}
+
+struct Scheduler {
+    state: Mutex<State>,
+    turn: Condvar,
+}
+
+// Payload of the threads unwinding from an aborted execution
+struct Aborted;
+
+thread_local! {
+    // Explorer of the test closure run by the current thread
+    static EXPLORER: RefCell<Option<Explorer>> = const { RefCell::new(None) };
+    // Scheduler of the current thread and its index, when it is started by `threads()`
+    static CONTEXT: RefCell<Option<(Arc<Scheduler>, usize)>> = const { RefCell::new(None) };
+}
+
+impl Scheduler {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Hand over to the thread chosen by the explorer, and wait for `me` to be chosen again unless
+    // it has finished
+    fn schedule(&self, me: usize) {
+        let mut state = self.lock();
+        if state.aborted {
+            return;
+        }
+        let runnable: Vec<usize> = (0..state.finished.len())
+            .filter(|&thread| !state.finished[thread])
+            .collect();
+        let Some(&first) = runnable.first() else {
+            return;
+        };
+        let can_continue = runnable.contains(&me);
+
+        let position = state.position;
+        let chosen = if let Some(choice) = state.path.get(position) {
+            assert!(
+                runnable.contains(&choice.chosen),
+                "Non-deterministic execution, the replayed thread is not runnable"
+            );
+            choice.chosen
+        } else {
+            let chosen = if can_continue { me } else { first };
+            let alternatives = if can_continue && state.nb_preemptions >= state.max_preemptions {
+                Vec::new()
+            } else {
+                runnable
+                    .iter()
+                    .rev()
+                    .copied()
+                    .filter(|&thread| thread != chosen)
+                    .collect()
+            };
+            state.path.push(Choice {
+                chosen,
+                alternatives,
+            });
+            chosen
+        };
+        state.position += 1;
+        if can_continue && chosen != me {
+            state.nb_preemptions += 1;
+        }
+        state.active = chosen;
+        self.turn.notify_all();
+
+        if can_continue {
+            self.wait_turn(state, me);
+        }
+    }
+
+    fn wait_turn(&self, mut state: MutexGuard<'_, State>, me: usize) {
+        while state.active != me {
+            if state.aborted {
+                drop(state);
+                panic::resume_unwind(Box::new(Aborted));
+            }
+            state = self
+                .turn
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn finish(&self, me: usize) {
+        self.lock().finished[me] = true;
+        self.schedule(me);
+    }
+
+    fn abort(&self) {
+        self.lock().aborted = true;
+        self.turn.notify_all();
+    }
+}
+
+// Scheduling point of the current thread, a no-op outside `threads()`
+fn switch() {
+    let context = CONTEXT.with(|context| context.borrow().clone());
+    if let Some((scheduler, me)) = context {
+        scheduler.schedule(me);
+    }
+}
+
+// Explore the interleavings of the threads started by `f`, and return the number of executions
+pub fn check<F: Fn()>(f: F) -> usize {
+    check_with_max_preemptions(DEFAULT_MAX_PREEMPTIONS, f)
+}
+
+pub fn check_with_max_preemptions<F: Fn()>(max_preemptions: usize, f: F) -> usize {
+    let mut path = Vec::new();
+    let mut nb_executions = 0;
+    loop {
+        EXPLORER.with(|explorer| {
+            *explorer.borrow_mut() = Some(Explorer {
+                path,
+                max_preemptions,
+            })
+        });
+        f();
+        path = EXPLORER
+            .with(|explorer| explorer.borrow_mut().take())
+            .unwrap()
+            .path;
+        nb_executions += 1;
+
+        // The last choice having an alternative left is changed, the following ones are dropped
+        loop {
+            let Some(choice) = path.last_mut() else {
+                return nb_executions;
+            };
+            if let Some(alternative) = choice.alternatives.pop() {
+                choice.chosen = alternative;
+                break;
+            }
+            path.pop();
+        }
+    }
+}
+
+// Run the threads of an execution of `check()`, returning once all of them have finished. A
+// panic of one of them is propagated once the other ones have been unwound.
+pub fn threads(bodies: &[&(dyn Fn() + Sync)]) {
+    let Explorer {
+        path,
+        max_preemptions,
+    } = EXPLORER
+        .with(|explorer| explorer.borrow_mut().take())
+        .expect("model::threads() must be called once per execution of model::check()");
+    let scheduler = Arc::new(Scheduler {
+        state: Mutex::new(State {
+            active: usize::MAX,
+            finished: vec![false; bodies.len()],
+            path,
+            position: 0,
+            nb_preemptions: 0,
+            max_preemptions,
+            aborted: false,
+        }),
+        turn: Condvar::new(),
+    });
+
+    let failure = std::thread::scope(|scope| {
+        let handles: Vec<_> = bodies
+            .iter()
+            .enumerate()
+            .map(|(me, body)| {
+                let scheduler = scheduler.clone();
+                scope.spawn(move || {
+                    CONTEXT.with(|context| *context.borrow_mut() = Some((scheduler.clone(), me)));
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                        scheduler.wait_turn(scheduler.lock(), me);
+                        body();
+                    }));
+                    CONTEXT.with(|context| context.borrow_mut().take());
+                    match outcome {
+                        Ok(()) => {
+                            scheduler.finish(me);
+                            None
+                        }
+                        Err(payload) => {
+                            scheduler.abort();
+                            (!payload.is::<Aborted>()).then_some(payload)
+                        }
+                    }
+                })
+            })
+            .collect();
+        // The first thread to run is chosen like the following ones
+        scheduler.schedule(usize::MAX);
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .next()
+    });
+
+    let mut state = scheduler.lock();
+    if let Some(payload) = failure {
+        let interleaving: Vec<_> = state.path[..state.position]
+            .iter()
+            .map(|choice| choice.chosen)
+            .collect();
+        eprintln!("Failing interleaving: {interleaving:?}");
+        drop(state);
+        panic::resume_unwind(payload);
+    }
+    assert_eq!(
+        state.position,
+        state.path.len(),
+        "Non-deterministic execution, the replayed choices have not all been reached"
+    );
+    let path = std::mem::take(&mut state.path);
+    EXPLORER.with(|explorer| {
+        *explorer.borrow_mut() = Some(Explorer {
+            path,
+            max_preemptions,
+        })
+    });
+}
+
+// Atomics handing over to the explorer before each operation. Outside `threads()`, they behave as
+// the ones of `portable_atomic`.
+pub mod atomic {
+    use super::switch;
+    use portable_atomic::Ordering;
+
+    macro_rules! atomic_int {
+        ($($atomic:ident: $int:ty),*) => {$(
+            pub struct $atomic {
+                inner: portable_atomic::$atomic,
+            }
+
+            impl $atomic {
+                pub const fn new(value: $int) -> Self {
+                    Self {
+                        inner: portable_atomic::$atomic::new(value),
+                    }
+                }
+
+                pub fn load(&self, order: Ordering) -> $int {
+                    switch();
+                    self.inner.load(order)
+                }
+
+                pub fn store(&self, value: $int, order: Ordering) {
+                    switch();
+                    self.inner.store(value, order)
+                }
+
+                pub fn swap(&self, value: $int, order: Ordering) -> $int {
+                    switch();
+                    self.inner.swap(value, order)
+                }
+
+                pub fn compare_exchange(
+                    &self,
+                    current: $int,
+                    new: $int,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$int, $int> {
+                    switch();
+                    self.inner.compare_exchange(current, new, success, failure)
+                }
+
+                // Never fails spuriously, so that the executions are deterministic
+                pub fn compare_exchange_weak(
+                    &self,
+                    current: $int,
+                    new: $int,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$int, $int> {
+                    self.compare_exchange(current, new, success, failure)
+                }
+
+                pub fn fetch_add(&self, value: $int, order: Ordering) -> $int {
+                    switch();
+                    self.inner.fetch_add(value, order)
+                }
+
+                pub fn fetch_sub(&self, value: $int, order: Ordering) -> $int {
+                    switch();
+                    self.inner.fetch_sub(value, order)
+                }
+
+                pub fn fetch_min(&self, value: $int, order: Ordering) -> $int {
+                    switch();
+                    self.inner.fetch_min(value, order)
+                }
+
+                // A single operation, as no other thread runs until the next scheduling point
+                pub fn fetch_update<F>(
+                    &self,
+                    set_order: Ordering,
+                    fetch_order: Ordering,
+                    f: F,
+                ) -> Result<$int, $int>
+                where
+                    F: FnMut($int) -> Option<$int>,
+                {
+                    switch();
+                    self.inner.fetch_update(set_order, fetch_order, f)
+                }
+            }
+        )*};
+    }
+
+    atomic_int!(AtomicUsize: usize, AtomicU32: u32, AtomicU64: u64, AtomicU128: u128);
+
+    pub struct AtomicPtr<T> {
+        inner: portable_atomic::AtomicPtr<T>,
+    }
+
+    impl<T> AtomicPtr<T> {
+        pub const fn new(value: *mut T) -> Self {
+            Self {
+                inner: portable_atomic::AtomicPtr::new(value),
+            }
+        }
+
+        pub fn load(&self, order: Ordering) -> *mut T {
+            switch();
+            self.inner.load(order)
+        }
+
+        pub fn store(&self, value: *mut T, order: Ordering) {
+            switch();
+            self.inner.store(value, order)
+        }
+
+        pub fn swap(&self, value: *mut T, order: Ordering) -> *mut T {
+            switch();
+            self.inner.swap(value, order)
+        }
+
+        pub fn compare_exchange(
+            &self,
+            current: *mut T,
+            new: *mut T,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<*mut T, *mut T> {
+            switch();
+            self.inner.compare_exchange(current, new, success, failure)
+        }
+
+        pub fn compare_exchange_weak(
+            &self,
+            current: *mut T,
+            new: *mut T,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<*mut T, *mut T> {
+            self.compare_exchange(current, new, success, failure)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::atomic::AtomicUsize;
+    use super::*;
+    use portable_atomic::Ordering;
+
+    #[test]
+    fn exhaustive_test() {
+        // Each thread runs 3 steps: up to its first store, up to its second one, and up to its
+        // end. Without bound, every one of the C(6, 3) orders of these steps is explored.
+        let nb_executions = check_with_max_preemptions(usize::MAX, || {
+            let value = AtomicUsize::new(0);
+            let store_twice = || {
+                value.store(1, Ordering::Relaxed);
+                value.store(2, Ordering::Relaxed);
+            };
+            threads(&[&store_twice, &store_twice]);
+        });
+        assert_eq!(nb_executions, 20);
+
+        // Without preemption, the threads only run one after the other
+        assert_eq!(
+            check_with_max_preemptions(0, || {
+                let value = AtomicUsize::new(0);
+                let store = || value.store(1, Ordering::Relaxed);
+                threads(&[&store, &store, &store]);
+            }),
+            6
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Lost update")]
+    fn lost_update_test() {
+        // Incrementing with a load and a store loses an update when the other thread runs
+        // between them, which a single preemption is enough to find
+        check_with_max_preemptions(1, || {
+            let counter = AtomicUsize::new(0);
+            let increment = || {
+                let value = counter.load(Ordering::Relaxed);
+                counter.store(value + 1, Ordering::Relaxed);
+            };
+            threads(&[&increment, &increment]);
+            assert_eq!(counter.load(Ordering::Relaxed), 2, "Lost update");
+        });
+    }
+}
@@ -0,0 +1,246 @@
+use core::{cell::UnsafeCell, marker::PhantomData, ptr::NonNull};
+
+use portable_atomic as atomic;
+
+use atomic::Ordering;
+
+#[cfg_attr(
+    not(all(target_arch = "arm", target_has_atomic = "32")),
+    path = "mpsc/cas.rs"
+)]
+#[cfg_attr(
+    all(target_arch = "arm", target_has_atomic = "32"),
+    path = "mpsc/llsc.rs"
+)]
+mod impl_;
+
+/// Link to the next node, stored in each node while it is in a `Queue`
+pub struct Link {
+    next: atomic::AtomicPtr<Link>,
+}
+
+impl Link {
+    pub const fn new() -> Self {
+        Self {
+            next: atomic::AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Node of a `Queue`, holding a `Link` while it is in the queue
+///
+/// # Safety
+/// The node must be `#[repr(C)]`, its link being its first field, so that the pointers to a node
+/// and to its link are interchangeable (e.g. the start of a free pool slot).
+pub unsafe trait Node: Sized {}
+
+/// Intrusive multi-producer single-consumer FIFO queue (Vyukov queue) of nodes owned by the
+/// caller.
+///
+/// Pushing is lock-free and can be done from any context, ISRs included: the last node is
+/// swapped with CAS, or with LDREX/STREX on the ARM cores providing them, and then linked to
+/// the previous one. Popping must be done from a single context at a time. A node whose
+/// producer has been preempted between these two steps is not visible to the consumer, the
+/// queue being observed as empty until it resumes.
+///
+/// The queue links its own stub node, so that it must not be moved once used, typically being
+/// a static.
+pub struct Queue<N>
+where
+    N: Node,
+{
+    // Last node pushed, null standing for the stub
+    head: atomic::AtomicPtr<Link>,
+    // Next node to pop, only accessed by the consumer. Null stands for the stub.
+    tail: UnsafeCell<*mut Link>,
+    stub: Link,
+    _marker: PhantomData<NonNull<N>>,
+}
+
+// SAFETY: the nodes are handed from the producers to the consumer through the atomic links
+unsafe impl<N> Sync for Queue<N> where N: Node + Send {}
+unsafe impl<N> Send for Queue<N> where N: Node + Send {}
+
+impl<N> Queue<N>
+where
+    N: Node,
+{
+    pub const fn new() -> Self {
+        Self {
+            head: atomic::AtomicPtr::new(core::ptr::null_mut()),
+            tail: UnsafeCell::new(core::ptr::null_mut()),
+            stub: Link::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn stub(&self) -> *mut Link {
+        &self.stub as *const Link as *mut Link
+    }
+
+    #[inline]
+    fn or_stub(&self, link: *mut Link) -> *mut Link {
+        if link.is_null() {
+            self.stub()
+        } else {
+            link
+        }
+    }
+
+    /// # Safety
+    /// - `node` must be a valid pointer, and stay valid until it is popped
+    /// - aliasing rules must be enforced by the caller. e.g, the same `node` may not be pushed
+    ///   more than once
+    pub unsafe fn push(&self, node: NonNull<N>) {
+        // Cast rather than borrowed, so that the popped pointer covers the whole node
+        self.push_link(node.as_ptr().cast())
+    }
+
+    unsafe fn push_link(&self, link: *mut Link) {
+        (*link).next.store(core::ptr::null_mut(), Ordering::Relaxed);
+        let prev = self.or_stub(impl_::swap(&self.head, link));
+        // The queue is broken until the previous node is linked to the new one
+        (*prev).next.store(link, Ordering::Release);
+    }
+
+    /// # Safety
+    /// Must not be called from several contexts at the same time
+    pub unsafe fn pop(&self) -> Option<NonNull<N>> {
+        let stub = self.stub();
+        let mut tail = self.or_stub(*self.tail.get());
+        let mut next = (*tail).next.load(Ordering::Acquire);
+
+        // The stub is skipped
+        if tail == stub {
+            if next.is_null() {
+                return None;
+            }
+            *self.tail.get() = next;
+            tail = next;
+            next = (*next).next.load(Ordering::Acquire);
+        }
+
+        if !next.is_null() {
+            *self.tail.get() = next;
+            return Some(NonNull::new_unchecked(tail).cast());
+        }
+
+        // The tail is the last node of the queue, unless a producer is linking a new one
+        if tail != self.or_stub(self.head.load(Ordering::Acquire)) {
+            return None;
+        }
+
+        // The stub is pushed back so that the tail can be popped while keeping a node in the
+        // queue
+        self.push_link(stub);
+        next = (*tail).next.load(Ordering::Acquire);
+        if next.is_null() {
+            return None;
+        }
+        *self.tail.get() = next;
+        Some(NonNull::new_unchecked(tail).cast())
+    }
+}
+
+impl<N> Default for Queue<N>
+where
+    N: Node,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[repr(C)]
+    struct TestNode {
+        link: Link,
+        producer: usize,
+        value: usize,
+    }
+
+    unsafe impl Node for TestNode {}
+
+    fn new_node(producer: usize, value: usize) -> NonNull<TestNode> {
+        NonNull::from(Box::leak(Box::new(TestNode {
+            link: Link::new(),
+            producer,
+            value,
+        })))
+    }
+
+    fn pop_value(queue: &Queue<TestNode>) -> Option<usize> {
+        unsafe { queue.pop().map(|node| node.as_ref().value) }
+    }
+
+    #[test]
+    fn fifo_test() {
+        let queue = Queue::new();
+        let nodes: Vec<_> = (0..3).map(|value| new_node(0, value)).collect();
+        assert_eq!(pop_value(&queue), None);
+
+        // The queue goes through the stub each time it is emptied
+        for _ in 0..3 {
+            unsafe { queue.push(nodes[0]) };
+            assert_eq!(pop_value(&queue), Some(0));
+            assert_eq!(pop_value(&queue), None);
+        }
+
+        for node in nodes.iter() {
+            unsafe { queue.push(*node) };
+        }
+        assert_eq!(pop_value(&queue), Some(0));
+        unsafe { queue.push(nodes[0]) };
+        assert_eq!(
+            core::iter::from_fn(|| pop_value(&queue)).collect::<Vec<_>>(),
+            [1, 2, 0]
+        );
+        for node in nodes {
+            drop(unsafe { Box::from_raw(node.as_ptr()) });
+        }
+    }
+
+    #[test]
+    fn concurrent_queue_test() {
+        const NB_PRODUCERS: usize = 4;
+        const NB_NODES_PER_PRODUCER: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let queue = Arc::new(Queue::new());
+        let producers: Vec<_> = (0..NB_PRODUCERS)
+            .map(|producer| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for value in 0..NB_NODES_PER_PRODUCER {
+                        unsafe { queue.push(new_node(producer, value)) };
+                    }
+                })
+            })
+            .collect();
+
+        // Every node is popped once, in the order of its producer
+        let mut next_values = [0; NB_PRODUCERS];
+        let mut nb_popped = 0;
+        while nb_popped < NB_PRODUCERS * NB_NODES_PER_PRODUCER {
+            if let Some(node) = unsafe { queue.pop() } {
+                let node = unsafe { Box::from_raw(node.as_ptr()) };
+                assert_eq!(node.value, next_values[node.producer]);
+                next_values[node.producer] += 1;
+                nb_popped += 1;
+            }
+        }
+        producers
+            .into_iter()
+            .for_each(|producer| producer.join().unwrap());
+        assert_eq!(unsafe { queue.pop() }.map(|_| ()), None);
+    }
+}
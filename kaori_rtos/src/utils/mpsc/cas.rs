@@ -0,0 +1,11 @@
+use portable_atomic as atomic;
+
+use atomic::Ordering;
+
+use super::Link;
+
+/// Swap the last node of the queue
+#[inline]
+pub fn swap(head: &atomic::AtomicPtr<Link>, link: *mut Link) -> *mut Link {
+    head.swap(link, Ordering::AcqRel)
+}
@@ -0,0 +1,20 @@
+use portable_atomic as atomic;
+
+use super::Link;
+use crate::utils::llsc as arch;
+
+/// Swap the last node of the queue
+#[inline]
+pub fn swap(head: &atomic::AtomicPtr<Link>, link: *mut Link) -> *mut Link {
+    let head_addr = head.as_ptr() as *mut usize;
+
+    unsafe {
+        loop {
+            let prev = arch::load_link(head_addr);
+
+            if arch::store_conditional(link as usize, head_addr).is_ok() {
+                break prev as *mut Link;
+            }
+        }
+    }
+}
@@ -1,55 +1,264 @@
-use core::mem::ManuallyDrop;
+use core::ptr::NonNull;
 
-#[cfg_attr(not(arm_llsc), path = "treiber/cas.rs")]
-#[cfg_attr(arm_llsc, path = "treiber/llsc.rs")]
+#[cfg_attr(
+    not(all(target_arch = "arm", target_has_atomic = "32")),
+    path = "treiber/cas.rs"
+)]
+#[cfg_attr(
+    all(target_arch = "arm", target_has_atomic = "32"),
+    path = "treiber/llsc.rs"
+)]
 mod impl_;
 
-pub use impl_::{AtomicPtr, NonNullPtr};
+pub use impl_::AtomicPtr;
 
-pub struct Stack
+/// Intrusive lock-free stack (Treiber stack) of nodes owned by the caller.
+///
+/// The top of the stack is swapped with a tagged CAS (a 64-bit word on 32-bit targets, a
+/// 128-bit one on 64-bit targets) or with LDREX/STREX on the ARM cores providing them.
+pub struct Stack<N>
+where
+    N: Node,
 {
-    top: AtomicPtr<Node>,
+    top: impl_::Top<N>,
 }
 
-impl Stack
+// SAFETY: the nodes are only handed from a context to another through the atomic top
+unsafe impl<N> Sync for Stack<N> where N: Node + Send {}
+unsafe impl<N> Send for Stack<N> where N: Node + Send {}
+
+impl<N> Stack<N>
+where
+    N: Node,
 {
     pub const fn new() -> Self {
         Self {
-            top: AtomicPtr::null(),
+            top: impl_::Top::null(),
         }
     }
 
     /// # Safety
-    /// - `node` must be a valid pointer
-    /// - aliasing rules must be enforced by the caller. e.g, the same `node` may not be pushed more than once
-    pub unsafe fn push(&self, node: NonNullPtr<Node>) {
-        impl_::push(self, node)
+    /// - `node` must be a valid pointer, and stay valid until it is popped
+    /// - aliasing rules must be enforced by the caller. e.g, the same `node` may not be pushed
+    ///   more than once
+    /// - the nodes of the stack must be in the same allocation, such as the storage of a pool,
+    ///   with pointers derived from the whole of it
+    pub unsafe fn push(&self, node: NonNull<N>) {
+        impl_::push(self, node, || {})
     }
 
-    pub fn try_pop(&self) -> Option<NonNullPtr<Node>> {
-        impl_::try_pop(self)
+    pub fn try_pop(&self) -> Option<NonNull<N>> {
+        impl_::try_pop(self, || {})
     }
-}
 
-pub struct Node {
-    pub next: ManuallyDrop<AtomicPtr<Node>>,
-}
-
-impl Node{
+    /// Same as `push()`, `on_retry` being called each time another context modified the stack
+    /// in the meantime
+    ///
+    /// # Safety
+    /// See `push()`
+    pub unsafe fn push_with<R>(&self, node: NonNull<N>, on_retry: R)
+    where
+        R: FnMut(),
+    {
+        impl_::push(self, node, on_retry)
+    }
 
-    fn next(&self) -> &AtomicPtr<Self> {
-        &self.next
+    pub fn try_pop_with<R>(&self, on_retry: R) -> Option<NonNull<N>>
+    where
+        R: FnMut(),
+    {
+        impl_::try_pop(self, on_retry)
     }
+}
 
-    fn next_mut(&mut self) -> &mut AtomicPtr<Self> {
-        &mut self.next
+impl<N> Default for Stack<N>
+where
+    N: Node,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Node of a `Stack`, holding the link to the next node while it is on the stack
+pub trait Node: Sized {
+    fn next(&self) -> &AtomicPtr<Self>;
+
+    fn next_mut(&mut self) -> &mut AtomicPtr<Self>;
+}
+
 #[cfg(test)]
 mod tests {
     use core::mem;
 
     use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    struct TestNode {
+        next: AtomicPtr<TestNode>,
+        value: usize,
+        // Set while the node is owned by a context, out of the stack
+        popped: AtomicBool,
+    }
+
+    impl Node for TestNode {
+        fn next(&self) -> &AtomicPtr<Self> {
+            &self.next
+        }
+
+        fn next_mut(&mut self) -> &mut AtomicPtr<Self> {
+            &mut self.next
+        }
+    }
+
+    fn new_nodes(nb_nodes: usize) -> Vec<TestNode> {
+        (0..nb_nodes)
+            .map(|value| TestNode {
+                next: AtomicPtr::null(),
+                value,
+                popped: AtomicBool::new(false),
+            })
+            .collect()
+    }
 
+    // The pointers to the nodes of a stack are derived from the whole allocation holding them
+    fn pointers(nodes: &mut Vec<TestNode>) -> Vec<NonNull<TestNode>> {
+        let first = nodes.as_mut_ptr();
+        (0..nodes.len())
+            .map(|index| unsafe { NonNull::new_unchecked(first.add(index)) })
+            .collect()
+    }
+
+    fn value(node: NonNull<TestNode>) -> usize {
+        unsafe { node.as_ref().value }
+    }
+
+    #[test]
+    fn stack_test() {
+        // The links are not tagged, only the top is
+        assert_eq!(
+            mem::size_of::<AtomicPtr<TestNode>>(),
+            mem::size_of::<usize>()
+        );
+
+        let stack = Stack::new();
+        let mut storage = new_nodes(3);
+        let nodes = pointers(&mut storage);
+        assert!(stack.try_pop().is_none());
+        for node in nodes.iter() {
+            unsafe { stack.push(*node) };
+        }
+        assert_eq!(stack.try_pop().map(value), Some(2));
+        unsafe { stack.push(nodes[2]) };
+        assert_eq!(
+            core::iter::from_fn(|| stack.try_pop().map(value)).collect::<Vec<_>>(),
+            [2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn concurrent_stack_test() {
+        const NB_THREADS: usize = 4;
+        const NB_NODES: usize = 8;
+        const NB_ITERATIONS: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let stack = Arc::new(Stack::new());
+        let mut storage = new_nodes(NB_NODES);
+        for node in pointers(&mut storage) {
+            unsafe { stack.push(node) };
+        }
+        let threads: Vec<_> = (0..NB_THREADS)
+            .map(|_| {
+                let stack = stack.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..NB_ITERATIONS {
+                        // Popping two nodes and pushing them back in the reverse order puts
+                        // the same node back on top while other threads are popping it
+                        let first = stack.try_pop();
+                        let second = stack.try_pop();
+                        for node in [first, second].into_iter().flatten() {
+                            unsafe { stack.push(node) };
+                        }
+                    }
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+
+        // No node has been lost nor duplicated
+        let mut values: Vec<_> = core::iter::from_fn(|| stack.try_pop().map(value)).collect();
+        values.sort();
+        assert_eq!(values, (0..NB_NODES).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(kaori_model)]
+    fn model_stack_test() {
+        use crate::utils::model;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Popping a node handed out to another context, or pushing one back that is already on
+        // the stack, is caught by the `popped` flag of the nodes
+        fn pop(stack: &Stack<TestNode>) -> Option<NonNull<TestNode>> {
+            let node = stack.try_pop()?;
+            assert!(
+                !unsafe { node.as_ref() }
+                    .popped
+                    .swap(true, Ordering::Relaxed),
+                "Node {} popped twice",
+                value(node)
+            );
+            Some(node)
+        }
+
+        fn push(stack: &Stack<TestNode>, node: NonNull<TestNode>) {
+            unsafe {
+                node.as_ref().popped.store(false, Ordering::Relaxed);
+                stack.push(node);
+            }
+        }
+
+        let mut storage = new_nodes(2);
+        let nodes = pointers(&mut storage);
+        let nb_executions = model::check(|| {
+            let stack = Stack::new();
+            for node in nodes.iter() {
+                push(&stack, *node);
+            }
+            let kept = AtomicUsize::new(usize::MAX);
+            // The ABA scenario: the first thread reads the top and its next node, the second
+            // one pops both nodes, keeps the next one and pushes back the top one
+            let pop_push = || {
+                if let Some(node) = pop(&stack) {
+                    push(&stack, node);
+                }
+            };
+            let pop_twice_push = || {
+                let first = pop(&stack);
+                if let Some(second) = pop(&stack) {
+                    kept.store(value(second), Ordering::Relaxed);
+                }
+                if let Some(first) = first {
+                    push(&stack, first);
+                }
+            };
+            model::threads(&[&pop_push, &pop_twice_push]);
+
+            // No node has been lost nor duplicated
+            let mut values: Vec<_> = core::iter::from_fn(|| pop(&stack).map(value))
+                .chain([kept.load(Ordering::Relaxed)])
+                .filter(|&value| value != usize::MAX)
+                .collect();
+            values.sort();
+            assert_eq!(values, [0, 1]);
+            for node in nodes.iter() {
+                unsafe { node.as_ref() }
+                    .popped
+                    .store(false, Ordering::Relaxed);
+            }
+        });
+        assert!(nb_executions > 1);
+    }
 }
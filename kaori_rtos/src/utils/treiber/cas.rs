@@ -1,9 +1,6 @@
-use core::{marker::PhantomData, ptr::NonNull};
+use core::ptr::NonNull;
 
-#[cfg(not(feature = "portable-atomic"))]
-use core::sync::atomic;
-#[cfg(feature = "portable-atomic")]
-use portable_atomic as atomic;
+use crate::utils::atomic;
 
 use atomic::Ordering;
 
@@ -15,9 +12,8 @@ mod types {
 
     pub type Inner = u64;
     pub type InnerAtomic = atomic::AtomicU64;
-    pub type InnerNonZero = core::num::NonZeroU64;
 
-    pub type Tag = core::num::NonZeroU32;
+    pub type Tag = u32;
     pub type Address = u32;
 }
 
@@ -27,20 +23,19 @@ mod types {
 
     pub type Inner = u128;
     pub type InnerAtomic = atomic::AtomicU128;
-    pub type InnerNonZero = core::num::NonZeroU128;
 
-    pub type Tag = core::num::NonZeroU64;
+    pub type Tag = u64;
     pub type Address = u64;
 }
 
 use types::*;
 
+/// Link to the next node, stored in each node
 pub struct AtomicPtr<N>
 where
     N: Node,
 {
-    inner: InnerAtomic,
-    _marker: PhantomData<*mut N>,
+    inner: atomic::AtomicPtr<N>,
 }
 
 impl<N> AtomicPtr<N>
@@ -50,217 +45,149 @@ where
     #[inline]
     pub const fn null() -> Self {
         Self {
-            inner: InnerAtomic::new(0),
-            _marker: PhantomData,
+            inner: atomic::AtomicPtr::new(core::ptr::null_mut()),
         }
     }
 
-    fn compare_and_exchange_weak(
-        &self,
-        current: Option<NonNullPtr<N>>,
-        new: Option<NonNullPtr<N>>,
-        success: Ordering,
-        failure: Ordering,
-    ) -> Result<(), Option<NonNullPtr<N>>> {
-        self.inner
-            .compare_exchange_weak(
-                current.map(NonNullPtr::into_inner).unwrap_or_default(),
-                new.map(NonNullPtr::into_inner).unwrap_or_default(),
-                success,
-                failure,
-            )
-            .map(drop)
-            .map_err(|value| {
-                // SAFETY: `value` cam from a `NonNullPtr::into_inner` call.
-                unsafe { NonNullPtr::from_inner(value) }
-            })
+    /// Clear the link of a node out of the stack, whose memory may have been reused in the
+    /// meantime. The store is atomic, as contexts popping with a stale top may still read it.
+    #[inline]
+    pub fn reset(&self) {
+        self.store(None, Ordering::Relaxed)
     }
 
     #[inline]
-    fn load(&self, order: Ordering) -> Option<NonNullPtr<N>> {
-        Some(NonNullPtr {
-            inner: InnerNonZero::new(self.inner.load(order))?,
-            _marker: PhantomData,
-        })
+    fn load(&self, order: Ordering) -> Option<NonNull<N>> {
+        NonNull::new(self.inner.load(order))
     }
 
     #[inline]
-    fn store(&self, value: Option<NonNullPtr<N>>, order: Ordering) {
+    fn store(&self, value: Option<NonNull<N>>, order: Ordering) {
         self.inner
-            .store(value.map(NonNullPtr::into_inner).unwrap_or_default(), order)
+            .store(value.map_or(core::ptr::null_mut(), NonNull::as_ptr), order)
     }
 }
 
-pub struct NonNullPtr<N>
+/// Top of the stack: address of the top node, and a tag incremented by every push and pop
+///
+/// As the tagged address has no provenance, the pointers to the nodes are rebuilt from the last
+/// node pushed, which is why the nodes of a stack must be in the same allocation.
+pub struct Top<N>
 where
     N: Node,
 {
-    inner: InnerNonZero,
-    _marker: PhantomData<*mut N>,
+    inner: InnerAtomic,
+    provenance: atomic::AtomicPtr<N>,
 }
 
-impl<N> Clone for NonNullPtr<N>
+impl<N> Top<N>
 where
     N: Node,
 {
-    fn clone(&self) -> Self {
-        *self
+    #[inline]
+    pub const fn null() -> Self {
+        Self {
+            inner: InnerAtomic::new(0),
+            provenance: atomic::AtomicPtr::new(core::ptr::null_mut()),
+        }
     }
 }
 
-impl<N> Copy for NonNullPtr<N> where N: Node {}
-
-impl<N> NonNullPtr<N>
+#[inline]
+fn address<N>(top: Inner, provenance: *mut N) -> Option<NonNull<N>>
 where
     N: Node,
 {
-    #[inline]
-    pub fn as_ptr(&self) -> *mut N {
-        self.inner.get() as *mut N
-    }
-
-    #[inline]
-    pub fn from_static_mut_ref(reference: &'static mut N) -> NonNullPtr<N> {
-        // SAFETY: `reference` is a static mutable reference, i.e. a valid pointer.
-        unsafe { Self::new_unchecked(initial_tag(), NonNull::from(reference)) }
-    }
-
-    /// # Safety
-    ///
-    /// - `ptr` must be a valid pointer.
-    #[inline]
-    unsafe fn new_unchecked(tag: Tag, ptr: NonNull<N>) -> Self {
-        let value =
-            (Inner::from(tag.get()) << Address::BITS) | Inner::from(ptr.as_ptr() as Address);
-
-        Self {
-            // SAFETY: `value` is constructed from a `Tag` which is non-zero and half the
-            //         size of the `InnerNonZero` type, and a `NonNull<N>` pointer.
-            inner: unsafe { InnerNonZero::new_unchecked(value) },
-            _marker: PhantomData,
-        }
-    }
-
-    /// # Safety
-    ///
-    /// - `value` must come from a `Self::into_inner` call.
-    #[inline]
-    unsafe fn from_inner(value: Inner) -> Option<Self> {
-        Some(Self {
-            inner: InnerNonZero::new(value)?,
-            _marker: PhantomData,
-        })
-    }
-
-    #[inline]
-    fn non_null(&self) -> NonNull<N> {
-        // SAFETY: `Self` can only be constructed using a `NonNull<N>`.
-        unsafe { NonNull::new_unchecked(self.as_ptr()) }
-    }
-
-    #[inline]
-    fn into_inner(self) -> Inner {
-        self.inner.get()
-    }
-
-    #[inline]
-    fn tag(&self) -> Tag {
-        // SAFETY: `self.inner` was constructed from a non-zero `Tag`.
-        unsafe { Tag::new_unchecked((self.inner.get() >> Address::BITS) as Address) }
-    }
-
-    fn increment_tag(&mut self) {
-        let new_tag = self.tag().checked_add(1).unwrap_or_else(initial_tag);
-
-        // SAFETY: `self.non_null()` is a valid pointer.
-        *self = unsafe { Self::new_unchecked(new_tag, self.non_null()) };
-    }
+    NonNull::new(provenance.with_addr(top as Address as usize))
 }
 
 #[inline]
-const fn initial_tag() -> Tag {
-    Tag::MIN
+fn next_top<N>(top: Inner, node: Option<NonNull<N>>) -> Inner
+where
+    N: Node,
+{
+    let tag = ((top >> Address::BITS) as Tag).wrapping_add(1);
+    let address = node.map_or(0, |node| node.as_ptr().addr() as Address);
+    (Inner::from(tag) << Address::BITS) | Inner::from(address)
 }
 
-pub unsafe fn push<N>(stack: &Stack<N>, new_top: NonNullPtr<N>)
+/// # Safety
+/// See `Stack::push()`
+pub unsafe fn push<N, R>(stack: &Stack<N>, new_top: NonNull<N>, mut on_retry: R)
 where
     N: Node,
+    R: FnMut(),
 {
-    let mut top = stack.top.load(Ordering::Relaxed);
+    stack
+        .top
+        .provenance
+        .store(new_top.as_ptr(), Ordering::Relaxed);
+    let mut top = stack.top.inner.load(Ordering::Relaxed);
 
     loop {
         new_top
-            .non_null()
             .as_ref()
             .next()
-            .store(top, Ordering::Relaxed);
+            .store(address(top, new_top.as_ptr()), Ordering::Relaxed);
 
-        if let Err(p) = stack.top.compare_and_exchange_weak(
+        match stack.top.inner.compare_exchange_weak(
             top,
-            Some(new_top),
+            next_top(top, Some(new_top)),
             Ordering::Release,
             Ordering::Relaxed,
         ) {
-            top = p;
-        } else {
-            return;
+            Ok(_) => return,
+            Err(current) => {
+                top = current;
+                on_retry();
+            }
         }
     }
 }
 
-pub fn try_pop<N>(stack: &Stack<N>) -> Option<NonNullPtr<N>>
+pub fn try_pop<N, R>(stack: &Stack<N>, mut on_retry: R) -> Option<NonNull<N>>
 where
     N: Node,
+    R: FnMut(),
 {
-    loop {
-        if let Some(mut top) = stack.top.load(Ordering::Acquire) {
-            let next = unsafe { top.non_null().as_ref().next().load(Ordering::Relaxed) };
-
-            if stack
-                .top
-                .compare_and_exchange_weak(Some(top), next, Ordering::Release, Ordering::Relaxed)
-                .is_ok()
-            {
-                // Prevent the ABA problem (https://en.wikipedia.org/wiki/Treiber_stack#Correctness).
-                //
-                // Without this, the following would be possible:
-                //
-                // | Thread 1                      | Thread 2                | Stack                        |
-                // |-------------------------------|-------------------------|------------------------------|
-                // | push((1, 1))                  |                         | (1, 1)                       |
-                // | push((1, 2))                  |                         | (1, 2) -> (1, 1)             |
-                // | p = try_pop()::load // (1, 2) |                         | (1, 2) -> (1, 1)             |
-                // |                               | p = try_pop() // (1, 2) | (1, 1)                       |
-                // |                               | push((1, 3))            | (1, 3) -> (1, 1)             |
-                // |                               | push(p)                 | (1, 2) -> (1, 3) -> (1, 1)   |
-                // | try_pop()::cas(p, p.next)     |                         | (1, 1)                       |
-                //
-                // As can be seen, the `cas` operation succeeds, wrongly removing pointer `3` from the stack.
-                //
-                // By incrementing the tag before returning the pointer, it cannot be pushed again with the,
-                // same tag, preventing the `try_pop()::cas(p, p.next)` operation from succeeding.
-                //
-                // With this fix, `try_pop()` in thread 2 returns `(2, 2)` and the comparison between
-                // `(1, 2)` and `(2, 2)` fails, restarting the loop and correctly removing the new top:
-                //
-                // | Thread 1                      | Thread 2                | Stack                        |
-                // |-------------------------------|-------------------------|------------------------------|
-                // | push((1, 1))                  |                         | (1, 1)                       |
-                // | push((1, 2))                  |                         | (1, 2) -> (1, 1)             |
-                // | p = try_pop()::load // (1, 2) |                         | (1, 2) -> (1, 1)             |
-                // |                               | p = try_pop() // (2, 2) | (1, 1)                       |
-                // |                               | push((1, 3))            | (1, 3) -> (1, 1)             |
-                // |                               | push(p)                 | (2, 2) -> (1, 3) -> (1, 1)   |
-                // | try_pop()::cas(p, p.next)     |                         | (2, 2) -> (1, 3) -> (1, 1)   |
-                // | p = try_pop()::load // (2, 2) |                         | (2, 2) -> (1, 3) -> (1, 1)   |
-                // | try_pop()::cas(p, p.next)     |                         | (1, 3) -> (1, 1)             |
-                top.increment_tag();
+    let mut top = stack.top.inner.load(Ordering::Acquire);
 
-                return Some(top);
+    loop {
+        // stack observed as empty
+        let node = address(top, stack.top.provenance.load(Ordering::Relaxed))?;
+        // The node may have been popped and reused by another context since the top was read,
+        // in which case the link is garbage but the CAS fails.
+        let next = unsafe { node.as_ref().next().load(Ordering::Relaxed) };
+
+        // Prevent the ABA problem (https://en.wikipedia.org/wiki/Treiber_stack#Correctness).
+        //
+        // Without the tag, the following would be possible:
+        //
+        // | Thread 1                      | Thread 2                | Stack            |
+        // |-------------------------------|-------------------------|------------------|
+        // | p = try_pop()::load // 2      |                         | 2 -> 1           |
+        // |                               | p = try_pop() // 2      | 1                |
+        // |                               | try_pop() // 1          |                  |
+        // |                               | push(3)                 | 3                |
+        // |                               | push(p)                 | 2 -> 3           |
+        // | try_pop()::cas(2, 1)          |                         | 1                |
+        //
+        // As can be seen, the `cas` operation succeeds, wrongly removing node `3` from the stack
+        // and putting back node `1`, still owned by thread 2.
+        //
+        // As every push and pop increments the tag of the top, the top `(t, 2)` read by thread
+        // 1 has become `(t + 4, 2)` when the `cas` operation happens, so that it fails.
+        match stack.top.inner.compare_exchange_weak(
+            top,
+            next_top(top, next),
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => return Some(node),
+            Err(current) => {
+                top = current;
+                on_retry();
             }
-        } else {
-            // stack observed as empty
-            return None;
         }
     }
 }
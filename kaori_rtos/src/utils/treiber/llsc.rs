@@ -4,7 +4,9 @@ use core::{
 };
 
 use super::{Node, Stack};
+use crate::utils::llsc as arch;
 
+/// Link to the next node, stored in each node
 pub struct AtomicPtr<N>
 where
     N: Node,
@@ -22,68 +24,48 @@ where
             inner: UnsafeCell::new(None),
         }
     }
-}
-
-pub struct NonNullPtr<N>
-where
-    N: Node,
-{
-    inner: NonNull<N>,
-}
 
-impl<N> NonNullPtr<N>
-where
-    N: Node,
-{
+    /// Clear the link of a node out of the stack, whose memory may have been reused in the
+    /// meantime. A plain store like the ones of `push()`, any store clearing the exclusive
+    /// monitor of the contexts popping with a stale top.
     #[inline]
-    pub fn as_ptr(&self) -> *mut N {
-        self.inner.as_ptr().cast()
-    }
-
-    #[inline]
-    pub fn from_static_mut_ref(ref_: &'static mut N) -> Self {
-        Self {
-            inner: NonNull::from(ref_),
-        }
+    pub fn reset(&self) {
+        unsafe { self.inner.get().write(None) }
     }
 }
 
-impl<N> Clone for NonNullPtr<N>
-where
-    N: Node,
-{
-    fn clone(&self) -> Self {
-        Self { inner: self.inner }
-    }
-}
+/// Top of the stack, no tag being needed as any store to it clears the exclusive monitor
+pub type Top<N> = AtomicPtr<N>;
 
-impl<N> Copy for NonNullPtr<N> where N: Node {}
-
-pub unsafe fn push<N>(stack: &Stack<N>, mut node: NonNullPtr<N>)
+/// # Safety
+/// See `Stack::push()`
+pub unsafe fn push<N, R>(stack: &Stack<N>, mut node: NonNull<N>, mut on_retry: R)
 where
     N: Node,
+    R: FnMut(),
 {
     let top_addr = ptr::addr_of!(stack.top) as *mut usize;
 
     loop {
         let top = arch::load_link(top_addr);
 
-        node.inner
-            .as_mut()
+        node.as_mut()
             .next_mut()
             .inner
             .get()
             .write(NonNull::new(top as *mut _));
 
-        if arch::store_conditional(node.inner.as_ptr() as usize, top_addr).is_ok() {
+        if arch::store_conditional(node.as_ptr() as usize, top_addr).is_ok() {
             break;
         }
+        on_retry();
     }
 }
 
-pub fn try_pop<N>(stack: &Stack<N>) -> Option<NonNullPtr<N>>
+pub fn try_pop<N, R>(stack: &Stack<N>, mut on_retry: R) -> Option<NonNull<N>>
 where
     N: Node,
+    R: FnMut(),
 {
     unsafe {
         let top_addr = ptr::addr_of!(stack.top) as *mut usize;
@@ -104,8 +86,9 @@ where
                 )
                 .is_ok()
                 {
-                    break Some(NonNullPtr { inner: top });
+                    break Some(top);
                 }
+                on_retry();
             } else {
                 arch::clear_load_link();
 
@@ -114,37 +97,3 @@ where
         }
     }
 }
-
-#[cfg(arm_llsc)]
-mod arch {
-    use core::arch::asm;
-
-    #[inline(always)]
-    pub fn clear_load_link() {
-        unsafe { asm!("clrex", options(nomem, nostack)) }
-    }
-
-    /// # Safety
-    ///
-    /// - `addr` must be a valid pointer.
-    #[inline(always)]
-    pub unsafe fn load_link(addr: *const usize) -> usize {
-        let value;
-        asm!("ldrex {}, [{}]", out(reg) value, in(reg) addr, options(nostack));
-        value
-    }
-
-    /// # Safety
-    ///
-    /// - `addr` must be a valid pointer.
-    #[inline(always)]
-    pub unsafe fn store_conditional(value: usize, addr: *mut usize) -> Result<(), ()> {
-        let outcome: usize;
-        asm!("strex {}, {}, [{}]", out(reg) outcome, in(reg) value, in(reg) addr, options(nostack));
-        if outcome == 0 {
-            Ok(())
-        } else {
-            Err(())
-        }
-    }
-}